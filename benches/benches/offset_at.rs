@@ -0,0 +1,51 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use lsp_textdocument::FullTextDocument;
+use lsp_types::{Position, Range, TextDocumentContentChangeEvent};
+
+fn long_lines(c: &mut Criterion) {
+    let line = "let text = \"\u{20AC} \u{10437}\"; ".repeat(200);
+    let content = format!("{line}\n").repeat(100);
+    let mut document = FullTextDocument::new("plain_text".to_string(), 1, content);
+    let positions: Vec<Position> = (0..100)
+        .flat_map(|line| {
+            (0..5000)
+                .step_by(97)
+                .map(move |character| Position::new(line, character))
+        })
+        .collect();
+
+    c.bench_function("offset_at on long lines", |b| {
+        b.iter(|| {
+            positions
+                .iter()
+                .map(|&position| document.offset_at(position))
+                .sum::<u32>()
+        })
+    });
+
+    c.bench_function("offsets_at on long lines", |b| {
+        b.iter(|| document.offsets_at(&positions).into_iter().sum::<u32>())
+    });
+
+    // typing on the first line keeps the index of the other lines
+    let mut version = 1;
+    c.bench_function("offset_at on long lines after an edit", |b| {
+        b.iter(|| {
+            version += 1;
+            let position = Position::new(0, 0);
+            let change = TextDocumentContentChangeEvent {
+                range: Some(Range::new(position, position)),
+                range_length: None,
+                text: "x".to_string(),
+            };
+            document.update(&[change], version);
+            positions
+                .iter()
+                .map(|&position| document.offset_at(position))
+                .sum::<u32>()
+        })
+    });
+}
+
+criterion_group!(benches, long_lines);
+criterion_main!(benches);
@@ -0,0 +1,13 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use lsp_textdocument::FullTextDocument;
+
+fn large_document(c: &mut Criterion) {
+    let content = "let value = \"\u{20AC}\";\r\n".repeat(200_000);
+
+    c.bench_function("open a large document", |b| {
+        b.iter(|| FullTextDocument::new("plain_text".to_string(), 1, content.clone()).line_count())
+    });
+}
+
+criterion_group!(benches, large_document);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use lsp_textdocument::FullTextDocument;
+use lsp_types::{Position, Range, TextDocumentContentChangeEvent};
+
+fn change(range: Range, text: &str) -> TextDocumentContentChangeEvent {
+    TextDocumentContentChangeEvent {
+        range: Some(range),
+        range_length: None,
+        text: text.to_string(),
+    }
+}
+
+fn top_of_file_edits(c: &mut Criterion) {
+    let content = "let value = 42;\n".repeat(100_000);
+    let mut document = FullTextDocument::new("plain_text".to_string(), 1, content);
+    let start = Position::new(0, 0);
+
+    c.bench_function("insert and delete a character", |b| {
+        b.iter(|| {
+            document
+                .apply_change(&change(Range::new(start, start), "a"))
+                .unwrap();
+            document
+                .apply_change(&change(Range::new(start, Position::new(0, 1)), ""))
+                .unwrap();
+        })
+    });
+
+    c.bench_function("insert and delete a line", |b| {
+        b.iter(|| {
+            document
+                .apply_change(&change(Range::new(start, start), "\n"))
+                .unwrap();
+            document
+                .apply_change(&change(Range::new(start, Position::new(1, 0)), ""))
+                .unwrap();
+        })
+    });
+}
+
+criterion_group!(benches, top_of_file_edits);
+criterion_main!(benches);
@@ -68,7 +68,10 @@ fn main_loop(
                 eprintln!("got response: {:?}", resp);
             }
             Message::Notification(not) => {
-                if !documents.listen(not.method.as_str(), &not.params) {
+                if !documents
+                    .listen(not.method.as_str(), &not.params)
+                    .is_handled()
+                {
                     // Add handlers for other types of notifications here.
                 }
             }
@@ -0,0 +1,30 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lsp_textdocument::testing::{assert_consistent, changes_from_bytes};
+use lsp_textdocument::{FullTextDocument, PositionEncoding};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&encoding, data)) = data.split_first() else {
+        return;
+    };
+    let encoding = match encoding % 3 {
+        0 => PositionEncoding::Utf8,
+        1 => PositionEncoding::Utf16,
+        _ => PositionEncoding::Utf32,
+    };
+    let content = "\u{10437}a\r\nb\rc\n\u{20AC}".to_string();
+    let mut document =
+        FullTextDocument::with_encoding("plaintext".to_string(), 0, content, encoding);
+    let changes = changes_from_bytes(data);
+
+    // one change at a time, checking the document after each of them
+    for (version, change) in changes.iter().enumerate() {
+        let _ = document.try_update(std::slice::from_ref(change), version as i32 + 1);
+        assert_consistent(&document);
+    }
+
+    // and all of them at once
+    let _ = document.try_update(&changes, i32::MAX);
+    assert_consistent(&document);
+});
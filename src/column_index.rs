@@ -0,0 +1,165 @@
+use crate::PositionEncoding;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::{PoisonError, RwLock};
+
+/// Lines shorter than this many bytes are walked rather than indexed, building an
+/// index costs about as much as a walk
+pub(crate) const MIN_INDEXED_LINE_LEN: usize = 256;
+
+/// The non-ASCII characters of a line, every other character is one byte and one
+/// code unit long, so the byte offset of any column follows from them
+#[derive(Debug)]
+struct LineColumns {
+    /// The column, byte offset, code units and bytes of each non-ASCII character
+    chars: Vec<(u32, u32, u32, u32)>,
+}
+
+impl LineColumns {
+    fn new(line: &str, encoding: PositionEncoding) -> Self {
+        let mut column = 0;
+        let mut chars = Vec::new();
+        for (idx, char) in line.char_indices() {
+            let units = encoding.code_unit_len(char);
+            if !char.is_ascii() {
+                chars.push((column, idx as u32, units, char.len_utf8() as u32));
+            }
+            column += units;
+        }
+        Self { chars }
+    }
+
+    /// The byte offset of `character` within the line, rounding down inside
+    /// characters. Not clamped to the length of the line.
+    fn byte_offset(&self, character: u32) -> u32 {
+        let next = self
+            .chars
+            .partition_point(|&(column, ..)| column <= character);
+        match next.checked_sub(1).map(|idx| self.chars[idx]) {
+            Some((column, byte, units, bytes)) => {
+                if character < column + units {
+                    byte
+                } else {
+                    (byte + bytes).saturating_add(character - column - units)
+                }
+            }
+            None => character,
+        }
+    }
+}
+
+/// The lazily built columns of long lines, which make
+/// [`FullTextDocument::offset_at`](crate::FullTextDocument::offset_at) a binary
+/// search rather than a walk of the line
+///
+/// The index is behind a lock so that documents stay `Sync`, lookups of indexed
+/// lines only share it. An edit forgets the lines it touches and shifts the ones
+/// after it.
+#[derive(Debug, Default)]
+pub(crate) struct ColumnIndex(RwLock<BTreeMap<u32, LineColumns>>);
+
+impl ColumnIndex {
+    /// The byte offset of `character` within `line`, the text of line number
+    /// `line_number`, rounding down inside characters and clamped to the line
+    pub(crate) fn byte_offset(
+        &self,
+        line_number: u32,
+        line: &str,
+        character: u32,
+        encoding: PositionEncoding,
+    ) -> u32 {
+        let lines = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        let byte_offset = match lines.get(&line_number) {
+            Some(columns) => columns.byte_offset(character),
+            None => {
+                drop(lines);
+                let columns = LineColumns::new(line, encoding);
+                let byte_offset = columns.byte_offset(character);
+                self.0
+                    .write()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .insert(line_number, columns);
+                byte_offset
+            }
+        };
+        byte_offset.min(line.len() as u32)
+    }
+
+    /// Replace the lines in `lines` with `added` new lines, like
+    /// [`LineLenCache::splice`](crate::line_len_cache::LineLenCache::splice). The
+    /// line before them ends in the edited text, so it is forgotten too.
+    pub(crate) fn splice(&mut self, lines: Range<u32>, added: u32) {
+        let indexed = self.0.get_mut().unwrap_or_else(PoisonError::into_inner);
+        if indexed.is_empty() {
+            return;
+        }
+        let mut after = indexed.split_off(&lines.end);
+        indexed.split_off(&lines.start.saturating_sub(1));
+        if added != lines.end - lines.start {
+            after = after
+                .into_iter()
+                .map(|(line, columns)| (line - lines.end + lines.start + added, columns))
+                .collect();
+        }
+        indexed.append(&mut after);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.0
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+}
+
+/// A clone starts empty and is rebuilt lazily
+impl Clone for ColumnIndex {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_offset() {
+        let line = "a\u{20AC}b\u{10437}cd\r\n";
+        let columns = LineColumns::new(line, PositionEncoding::Utf16);
+        let offsets: Vec<u32> = (0..10).map(|c| columns.byte_offset(c)).collect();
+        assert_eq!(offsets, vec![0, 1, 4, 5, 5, 9, 10, 11, 12, 13]);
+
+        let columns = LineColumns::new(line, PositionEncoding::Utf32);
+        let offsets: Vec<u32> = (0..6).map(|c| columns.byte_offset(c)).collect();
+        assert_eq!(offsets, vec![0, 1, 4, 5, 9, 10]);
+
+        // far beyond the end of the line
+        assert_eq!(columns.byte_offset(u32::MAX), u32::MAX);
+
+        let index = ColumnIndex::default();
+        assert_eq!(index.byte_offset(0, line, 7, PositionEncoding::Utf16), 11);
+        assert_eq!(index.byte_offset(0, line, 99, PositionEncoding::Utf16), 13);
+        assert_eq!(
+            index.byte_offset(0, line, u32::MAX, PositionEncoding::Utf16),
+            13
+        );
+    }
+
+    #[test]
+    fn test_splice() {
+        let mut index = ColumnIndex::default();
+        for line in 0..6 {
+            index.byte_offset(line, "ab", 1, PositionEncoding::Utf16);
+        }
+        // lines 2 and 3 are replaced by 3 lines, line 1 ends in the edit
+        index.splice(2..4, 3);
+        let indexed: Vec<u32> = index.0.read().unwrap().keys().copied().collect();
+        assert_eq!(indexed, vec![0, 5, 6]);
+
+        // lines 1 and 2 are joined
+        index.splice(2..3, 0);
+        let indexed: Vec<u32> = index.0.read().unwrap().keys().copied().collect();
+        assert_eq!(indexed, vec![0, 4, 5]);
+    }
+}
@@ -0,0 +1,220 @@
+use std::ops::Range;
+
+/// Past this many differing lines the remaining differing lines are replaced at
+/// once, which bounds the memory of the diff to `O(MAX_EDIT_DISTANCE²)`
+const MAX_EDIT_DISTANCE: usize = 2048;
+
+/// The hunks that turn `old` into `new`, each one a byte range of `old` to replace
+/// with a byte range of `new`, in document order and not overlapping.
+///
+/// Lines are diffed with Myers' algorithm, then each hunk is narrowed to the
+/// characters that differ. A hunk never starts or ends between a `\r` and a `\n`.
+pub(crate) fn diff(old: &str, new: &str) -> Vec<(Range<usize>, Range<usize>)> {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let old_starts = line_starts(&old_lines);
+    let new_starts = line_starts(&new_lines);
+
+    diff_lines(&old_lines, &new_lines)
+        .into_iter()
+        .map(|(old_lines, new_lines)| {
+            let old_bytes = old_starts[old_lines.start]..old_starts[old_lines.end];
+            let new_bytes = new_starts[new_lines.start]..new_starts[new_lines.end];
+            narrow(old, old_bytes, new, new_bytes)
+        })
+        .filter(|(old_bytes, new_bytes)| !old_bytes.is_empty() || !new_bytes.is_empty())
+        .collect()
+}
+
+/// The byte offset of the start of each line, followed by the length of the text
+fn line_starts(lines: &[&str]) -> Vec<usize> {
+    let mut starts = Vec::with_capacity(lines.len() + 1);
+    let mut start = 0;
+    starts.push(start);
+    for line in lines {
+        start += line.len();
+        starts.push(start);
+    }
+    starts
+}
+
+/// The hunks of lines of `old` to replace with lines of `new`
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<(Range<usize>, Range<usize>)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut hunks = Vec::new();
+    // the end of the last matching lines, in `old` and `new`
+    let (mut old_end, mut new_end) = (0, 0);
+    for (x, y) in myers(old_middle, new_middle)
+        .into_iter()
+        .chain(std::iter::once((old_middle.len(), new_middle.len())))
+    {
+        if x > old_end || y > new_end {
+            hunks.push((prefix + old_end..prefix + x, prefix + new_end..prefix + y));
+        }
+        (old_end, new_end) = (x + 1, y + 1);
+    }
+    hunks
+}
+
+/// The pairs of indices of matching lines of a shortest edit script, in order
+fn myers(old: &[&str], new: &[&str]) -> Vec<(usize, usize)> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = (n + m) as usize;
+    // `v[k + offset]` is the furthest x reached on diagonal k = x - y
+    let offset = max as isize + 1;
+    let mut v = vec![0isize; 2 * max + 3];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    let mut found = false;
+    for d in 0..=max.min(MAX_EDIT_DISTANCE) as isize {
+        // only the diagonals next to the ones of this round are needed to backtrack
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                found = true;
+                break;
+            }
+        }
+        if found {
+            break;
+        }
+    }
+    if !found {
+        // too different, replace everything between the common prefix and suffix
+        return Vec::new();
+    }
+
+    let mut matches = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let idx = (k + d + 1) as usize;
+        let prev_k = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + d + 1) as usize];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        if d > 0 {
+            (x, y) = (prev_x, prev_y);
+        }
+    }
+    matches.reverse();
+    matches
+}
+
+/// Shrink a hunk by the characters its old and new text start and end with
+fn narrow(
+    old: &str,
+    old_bytes: Range<usize>,
+    new: &str,
+    new_bytes: Range<usize>,
+) -> (Range<usize>, Range<usize>) {
+    let (old_text, new_text) = (&old[old_bytes.clone()], &new[new_bytes.clone()]);
+    let mut prefix: usize = old_text
+        .chars()
+        .zip(new_text.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(char, _)| char.len_utf8())
+        .sum();
+    let mut suffix: usize = old_text[prefix..]
+        .chars()
+        .rev()
+        .zip(new_text[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(char, _)| char.len_utf8())
+        .sum();
+
+    // keep `\r\n` together, the `\r` is in both texts
+    if old_text[..prefix].ends_with('\r') && old[old_bytes.start + prefix..].starts_with('\n') {
+        prefix -= 1;
+    }
+    let old_end = old_bytes.end - suffix;
+    if old[..old_end].ends_with('\r') && old_text[old_text.len() - suffix..].starts_with('\n') {
+        suffix -= 1;
+    }
+
+    (
+        old_bytes.start + prefix..old_bytes.end - suffix,
+        new_bytes.start + prefix..new_bytes.end - suffix,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(old: &str, new: &str) -> String {
+        let mut text = old.to_string();
+        for (old_bytes, new_bytes) in diff(old, new).into_iter().rev() {
+            text.replace_range(old_bytes, &new[new_bytes]);
+        }
+        text
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(diff("hello\nrust", "hello\nrust"), vec![]);
+        assert_eq!(diff("", "hello"), vec![(0..0, 0..5)]);
+        assert_eq!(diff("hello", ""), vec![(0..5, 0..0)]);
+        assert_eq!(diff("a\nb\nc\n", "a\nx\nc\n"), vec![(2..3, 2..3)]);
+        assert_eq!(
+            diff("a\nb\nc\nd\n", "b\nc\nd2\ne\n"),
+            vec![(0..2, 0..0), (7..7, 5..8)]
+        );
+
+        let cases = [
+            (
+                "fn main() {\nlet a=1;\n}\n",
+                "fn main() {\n    let a = 1;\n}\n",
+            ),
+            ("a\r\nb\r\n", "a\nb\n"),
+            ("a\nb\n", "a\r\nb\r\n"),
+            ("a\r\nb", "a\r\nc"),
+            (
+                "\u{20AC}\u{10437}\n\u{10437}",
+                "\u{10437}\u{20AC}\n\u{20AC}",
+            ),
+            ("x\ny\nz", "z\ny\nx"),
+        ];
+        for (old, new) in cases {
+            assert_eq!(apply(old, new), new, "{old:?} -> {new:?}");
+        }
+    }
+
+    #[test]
+    fn test_diff_keeps_crlf_together() {
+        let hunks = diff("a\r\nb", "a\r\r\nb");
+        assert_eq!(hunks, vec![(1..3, 1..4)]);
+        let hunks = diff("a\r\nb", "a\rx\nb");
+        assert_eq!(hunks, vec![(1..3, 1..4)]);
+    }
+}
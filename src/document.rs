@@ -0,0 +1,178 @@
+use crate::{PositionEncoding, StaleVersionPolicy, UpdateError};
+use lsp_types::{Position, Range, TextDocumentContentChangeEvent, TextDocumentSyncKind};
+
+/// A text document that [`TextDocuments`](crate::TextDocuments) can track
+///
+/// Implement it for your own type to keep state derived from the content, e.g. a
+/// parse tree, next to the document rather than in a parallel map, or to store the
+/// content differently. The trait is object safe, code that only reads documents
+/// can take a `&dyn Document`.
+///
+/// # Examples
+///
+/// Basic usage:
+/// ```
+/// use lsp_textdocument::{Document, FullTextDocument, PositionEncoding, TextDocuments};
+/// use lsp_types::{Position, Range, TextDocumentContentChangeEvent};
+/// use serde_json::json;
+///
+/// struct CountedDocument {
+///     document: FullTextDocument,
+///     words: usize,
+/// }
+///
+/// impl Document for CountedDocument {
+///     fn new(language_id: String, version: i32, content: String) -> Self {
+///         Self::with_encoding(language_id, version, content, PositionEncoding::default())
+///     }
+///
+///     fn with_encoding(
+///         language_id: String,
+///         version: i32,
+///         content: String,
+///         position_encoding: PositionEncoding,
+///     ) -> Self {
+///         let words = content.split_whitespace().count();
+///         let document =
+///             FullTextDocument::with_encoding(language_id, version, content, position_encoding);
+///         Self { document, words }
+///     }
+///
+///     fn update(&mut self, changes: &[TextDocumentContentChangeEvent], version: i32) {
+///         self.document.update(changes, version);
+///         self.words = self.document.get_content(None).split_whitespace().count();
+///     }
+///
+///     fn version(&self) -> i32 {
+///         self.document.version()
+///     }
+///
+///     fn get_content(&self, range: Option<Range>) -> &str {
+///         self.document.get_content(range)
+///     }
+///
+///     fn line_count(&self) -> u32 {
+///         self.document.line_count()
+///     }
+///
+///     fn position_at(&self, offset: u32) -> Position {
+///         self.document.position_at(offset)
+///     }
+///
+///     fn offset_at(&self, position: Position) -> u32 {
+///         self.document.offset_at(position)
+///     }
+/// }
+///
+/// let mut text_documents = TextDocuments::<CountedDocument>::default();
+/// text_documents.listen("textDocument/didOpen", &json!({
+///     "textDocument": { "uri": "file://example.txt", "languageId": "plaintext", "version": 1, "text": "hello rust!" }
+/// }));
+///
+/// let uri = "file://example.txt".parse().unwrap();
+/// let document = text_documents.get_document(&uri).unwrap();
+/// assert_eq!(document.words, 2);
+///
+/// fn last_line(document: &dyn Document) -> &str {
+///     let line = document.line_count() - 1;
+///     let end = document.position_at(u32::MAX);
+///     document.get_content(Some(Range::new(Position::new(line, 0), end)))
+/// }
+/// assert_eq!(last_line(document), "hello rust!");
+/// ```
+pub trait Document {
+    /// Create a document, e.g. from a `textDocument/didOpen` notification
+    fn new(language_id: String, version: i32, content: String) -> Self
+    where
+        Self: Sized;
+
+    /// Create a document whose positions are counted in the given `encoding`, which
+    /// is ignored by default
+    fn with_encoding(
+        language_id: String,
+        version: i32,
+        content: String,
+        position_encoding: PositionEncoding,
+    ) -> Self
+    where
+        Self: Sized,
+    {
+        let _ = position_encoding;
+        Self::new(language_id, version, content)
+    }
+
+    /// Apply the changes of a `textDocument/didChange` notification, in order, and set
+    /// the version of the document, even if it isn't greater than the current one
+    fn update(&mut self, changes: &[TextDocumentContentChangeEvent], version: i32);
+
+    /// Like [`Document::update`], but takes ownership of the changes so that the text
+    /// of a full text replacement can be moved rather than copied. Defaults to
+    /// `update`.
+    fn update_owned(&mut self, changes: Vec<TextDocumentContentChangeEvent>, version: i32) {
+        self.update(&changes, version);
+    }
+
+    /// Like [`Document::update`], but returns an error instead of panicking when a
+    /// change can not be applied, as [`TextDocuments::listen`](crate::TextDocuments::listen)
+    /// does with the changes of the client. A stale `version` should be handled
+    /// according to the [`StaleVersionPolicy`]. Defaults to `update`, which never
+    /// fails.
+    fn try_update(
+        &mut self,
+        changes: &[TextDocumentContentChangeEvent],
+        version: i32,
+    ) -> Result<(), UpdateError> {
+        self.update(changes, version);
+        Ok(())
+    }
+
+    /// Like [`Document::try_update`], but takes ownership of the changes like
+    /// [`Document::update_owned`]. Defaults to `update_owned`, which never fails.
+    fn try_update_owned(
+        &mut self,
+        changes: Vec<TextDocumentContentChangeEvent>,
+        version: i32,
+    ) -> Result<(), UpdateError> {
+        self.update_owned(changes, version);
+        Ok(())
+    }
+
+    /// Replace the content with `text` and keep the version, e.g. with the saved text
+    /// of a `textDocument/didSave`. Defaults to [`Document::try_update_owned`] with a
+    /// full text change at the current version.
+    fn try_resync(&mut self, text: String) -> Result<(), UpdateError> {
+        let change = TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text,
+        };
+        let version = self.version();
+        self.try_update_owned(vec![change], version)
+    }
+
+    /// The version of the document
+    fn version(&self) -> i32;
+
+    /// The content of the document, or only the text in `range`
+    fn get_content(&self, range: Option<Range>) -> &str;
+
+    /// The amount of lines of the document
+    fn line_count(&self) -> u32;
+
+    /// Convert a byte offset of the content to a position, clamped to the content
+    fn position_at(&self, offset: u32) -> Position;
+
+    /// Convert a position to a byte offset of the content, clamped to the content
+    fn offset_at(&self, position: Position) -> u32;
+
+    /// Enforce the sync kind negotiated with the client, which is ignored by default
+    fn set_sync_kind(&mut self, sync_kind: TextDocumentSyncKind) {
+        let _ = sync_kind;
+    }
+
+    /// Configure how [`Document::try_update`] handles stale versions, which is
+    /// ignored by default
+    fn set_stale_version_policy(&mut self, stale_version_policy: StaleVersionPolicy) {
+        let _ = stale_version_policy;
+    }
+}
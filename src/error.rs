@@ -0,0 +1,130 @@
+use lsp_types::{Position, Range};
+use std::fmt;
+
+/// The reason a change could not be applied to a [`FullTextDocument`](crate::FullTextDocument)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateError {
+    /// The start of the change range is after its end
+    InvertedRange {
+        start: Position,
+        start_offset: u32,
+        end: Position,
+        end_offset: u32,
+    },
+    /// The change is older than the current document version
+    StaleVersion { current: i32, received: i32 },
+    /// The change carries a range, but the document is synced with
+    /// [`TextDocumentSyncKind::FULL`](lsp_types::TextDocumentSyncKind::FULL)
+    UnexpectedIncrementalChange { range: Range },
+    /// Two edits that refer to the same content, e.g. of
+    /// [`FullTextDocument::apply_text_edits`](crate::FullTextDocument::apply_text_edits),
+    /// replace some of the same text
+    OverlappingEdits { first: Range, second: Range },
+    /// The content would be longer than
+    /// [`FullTextDocument::max_supported_len`](crate::FullTextDocument::max_supported_len)
+    ContentTooLong { len: usize },
+}
+
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpdateError::InvertedRange {
+                start,
+                start_offset,
+                end,
+                end_offset,
+            } => write!(
+                f,
+                "Start offset must be less than end offset. {}:{} (offset {}) is not <= {}:{} (offset {})",
+                start.line, start.character, start_offset, end.line, end.character, end_offset
+            ),
+            UpdateError::StaleVersion { current, received } => write!(
+                f,
+                "Received version {received} is older than the current version {current}"
+            ),
+            UpdateError::UnexpectedIncrementalChange { range } => write!(
+                f,
+                "Received an incremental change at {}:{}-{}:{} for a fully synced document",
+                range.start.line, range.start.character, range.end.line, range.end.character
+            ),
+            UpdateError::OverlappingEdits { first, second } => write!(
+                f,
+                "Edits at {}:{}-{}:{} and {}:{}-{}:{} overlap",
+                first.start.line,
+                first.start.character,
+                first.end.line,
+                first.end.character,
+                second.start.line,
+                second.start.character,
+                second.end.line,
+                second.end.character
+            ),
+            UpdateError::ContentTooLong { len } => write!(
+                f,
+                "The content is {len} bytes long, longer than the supported {} bytes",
+                crate::FullTextDocument::max_supported_len()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UpdateError {}
+
+/// The reason a [`Position`] does not address the content of a
+/// [`FullTextDocument`](crate::FullTextDocument)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OffsetError {
+    /// The line is beyond the last line of the document
+    LineOutOfBounds { line: u32, line_count: u32 },
+    /// The character is beyond the end of the line, excluding its terminator
+    CharacterOutOfBounds {
+        line: u32,
+        character: u32,
+        line_len: u32,
+    },
+}
+
+impl fmt::Display for OffsetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OffsetError::LineOutOfBounds { line, line_count } => write!(
+                f,
+                "Line {line} is beyond the document, which has {line_count} lines"
+            ),
+            OffsetError::CharacterOutOfBounds {
+                line,
+                character,
+                line_len,
+            } => write!(
+                f,
+                "Character {character} is beyond the end of line {line}, which has length {line_len}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OffsetError {}
+
+/// The reason an offset does not address the content of a
+/// [`FullTextDocument`](crate::FullTextDocument)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PositionError {
+    /// The offset is beyond the end of the content
+    OffsetOutOfBounds { offset: u32, content_len: u32 },
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PositionError::OffsetOutOfBounds {
+                offset,
+                content_len,
+            } => write!(
+                f,
+                "Offset {offset} is beyond the end of the content, which has length {content_len}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
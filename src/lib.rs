@@ -1,10 +1,24 @@
 //!
 //! A LSP text documents manager that helps mapping of text document.
 //!
-//! The text documents [position-encoding](https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#positionEncodingKind) only supports `UTF-16`
+//! The text documents [position-encoding](https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#positionEncodingKind) defaults to `UTF-16`, `UTF-8` and `UTF-32` are supported as well
 
+mod column_index;
+mod diff;
+mod document;
+mod error;
+mod line_len_cache;
+mod position_encoding;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 mod text_document;
 mod text_documents;
 
-pub use text_document::FullTextDocument;
-pub use text_documents::TextDocuments;
+pub use document::Document;
+pub use error::{OffsetError, PositionError, UpdateError};
+pub use position_encoding::PositionEncoding;
+pub use text_document::{
+    FullTextDocument, FullTextDocumentBuilder, IndentKind, LineEnding, LineEndingCounts,
+    StaleVersionPolicy,
+};
+pub use text_documents::{ChangeKind, ListenOutcome, TextDocuments};
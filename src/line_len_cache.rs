@@ -0,0 +1,101 @@
+use std::ops::Range;
+use std::sync::{Mutex, PoisonError};
+
+/// A line whose length isn't computed yet, no line is this long since the content
+/// is shorter than `u32::MAX` bytes
+const UNKNOWN: u32 = u32::MAX;
+
+/// The lazily computed UTF-16 lengths of the lines of a document, see
+/// [`FullTextDocument::set_line_len_cache`](crate::FullTextDocument::set_line_len_cache)
+///
+/// Disabled by default, an enabled cache takes 4 bytes per line. The lengths are
+/// behind a mutex so that documents stay `Sync`.
+#[derive(Debug, Default)]
+pub(crate) struct LineLenCache(Option<Mutex<Vec<u32>>>);
+
+impl LineLenCache {
+    pub(crate) fn set_enabled(&mut self, enabled: bool, line_count: usize) {
+        if enabled != self.is_enabled() {
+            self.0 = enabled.then(|| Mutex::new(vec![UNKNOWN; line_count]));
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// The length of `line`, computed with `len` unless it is cached
+    pub(crate) fn get_or_insert_with(&self, line: u32, len: impl FnOnce() -> u32) -> u32 {
+        let Some(lens) = &self.0 else {
+            return len();
+        };
+        let mut lens = lens.lock().unwrap_or_else(PoisonError::into_inner);
+        match lens.get_mut(line as usize) {
+            Some(cached) => {
+                if *cached == UNKNOWN {
+                    *cached = len();
+                }
+                *cached
+            }
+            None => len(),
+        }
+    }
+
+    /// Forget every length, the document now has `line_count` lines
+    pub(crate) fn reset(&mut self, line_count: usize) {
+        if let Some(lens) = &mut self.0 {
+            let lens = lens.get_mut().unwrap_or_else(PoisonError::into_inner);
+            lens.clear();
+            lens.resize(line_count, UNKNOWN);
+        }
+    }
+
+    /// Replace the lines in `lines` with `added` new lines, like the line offsets of
+    /// an edit. The line before them ends in the edited text, so it is forgotten too.
+    pub(crate) fn splice(&mut self, lines: Range<usize>, added: usize) {
+        if let Some(lens) = &mut self.0 {
+            let lens = lens.get_mut().unwrap_or_else(PoisonError::into_inner);
+            if let Some(before) = lines.start.checked_sub(1) {
+                lens[before] = UNKNOWN;
+            }
+            lens.splice(lines, vec![UNKNOWN; added]);
+        }
+    }
+}
+
+impl Clone for LineLenCache {
+    fn clone(&self) -> Self {
+        Self(self.0.as_ref().map(|lens| {
+            let lens = lens.lock().unwrap_or_else(PoisonError::into_inner);
+            Mutex::new(lens.clone())
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_splice() {
+        let mut cache = LineLenCache::default();
+        assert_eq!(cache.get_or_insert_with(0, || 3), 3);
+
+        cache.set_enabled(true, 4);
+        for line in 0..4 {
+            cache.get_or_insert_with(line, || line);
+        }
+        // lines 1 and 2 are replaced by 3 lines, line 0 ends in the edit
+        cache.splice(1..3, 3);
+        let lens: Vec<u32> = (0..5)
+            .map(|line| cache.get_or_insert_with(line, || 9))
+            .collect();
+        assert_eq!(lens, vec![9, 9, 9, 9, 3]);
+        // beyond the cached lines
+        assert_eq!(cache.get_or_insert_with(5, || 7), 7);
+
+        cache.reset(2);
+        assert_eq!(cache.get_or_insert_with(0, || 1), 1);
+        assert_eq!(cache.clone().get_or_insert_with(0, || 2), 1);
+    }
+}
@@ -0,0 +1,55 @@
+use lsp_types::PositionEncodingKind;
+
+/// The encoding used to count the `character` column of a [`Position`](lsp_types::Position).
+///
+/// The LSP specification defaults to `UTF-16`, clients may negotiate another
+/// [position-encoding](https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#positionEncodingKind)
+/// during `initialize`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PositionEncoding {
+    /// Columns are counted in UTF-8 bytes
+    #[cfg_attr(feature = "serde", serde(rename = "utf-8"))]
+    Utf8,
+    /// Columns are counted in UTF-16 code units
+    #[default]
+    #[cfg_attr(feature = "serde", serde(rename = "utf-16"))]
+    Utf16,
+    /// Columns are counted in Unicode scalar values
+    #[cfg_attr(feature = "serde", serde(rename = "utf-32"))]
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// The amount of code units `char` occupies in this encoding
+    pub(crate) fn code_unit_len(self, char: char) -> u32 {
+        match self {
+            PositionEncoding::Utf8 => char.len_utf8() as u32,
+            PositionEncoding::Utf16 => char.len_utf16() as u32,
+            PositionEncoding::Utf32 => 1,
+        }
+    }
+}
+
+impl From<PositionEncoding> for PositionEncodingKind {
+    fn from(encoding: PositionEncoding) -> Self {
+        match encoding {
+            PositionEncoding::Utf8 => PositionEncodingKind::UTF8,
+            PositionEncoding::Utf16 => PositionEncodingKind::UTF16,
+            PositionEncoding::Utf32 => PositionEncodingKind::UTF32,
+        }
+    }
+}
+
+impl TryFrom<&PositionEncodingKind> for PositionEncoding {
+    type Error = ();
+
+    fn try_from(kind: &PositionEncodingKind) -> Result<Self, Self::Error> {
+        match kind.as_str() {
+            "utf-8" => Ok(PositionEncoding::Utf8),
+            "utf-16" => Ok(PositionEncoding::Utf16),
+            "utf-32" => Ok(PositionEncoding::Utf32),
+            _ => Err(()),
+        }
+    }
+}
@@ -0,0 +1,278 @@
+//! Helpers for property and fuzz tests of code that keeps documents in sync, so
+//! that they share one definition of a consistent document
+
+use crate::text_document::computed_line_offsets;
+use crate::FullTextDocument;
+use lsp_types::{Position, Range, TextDocumentContentChangeEvent};
+
+/// Asserts that the line offsets of the document match its content, as if they
+/// were computed from scratch, and that positions agree with them
+///
+/// # Panics
+///
+/// Panics if the document is inconsistent.
+///
+/// # Examples
+///
+/// Basic usage:
+/// ```
+/// use lsp_textdocument::testing::assert_consistent;
+/// use lsp_textdocument::FullTextDocument;
+///
+/// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\r\nrust!".to_string());
+/// assert_consistent(&document);
+/// ```
+pub fn assert_consistent(document: &FullTextDocument) {
+    let content = document.text();
+    assert_eq!(
+        document.line_offsets(),
+        computed_line_offsets(content, true, None),
+        "The line offsets are inconsistent with the content {content:?}"
+    );
+
+    let lines: String = (0..document.line_count())
+        .map(|line| {
+            let line_offset = document.line_offsets()[line as usize];
+            assert_eq!(
+                document.offset_at(Position::new(line, 0)),
+                line_offset,
+                "Line {line} doesn't start at its offset in {content:?}"
+            );
+            document
+                .line_with_terminator(line)
+                .expect("The line must be in the document")
+        })
+        .collect();
+    assert_eq!(lines, content, "The lines don't add up to the content");
+    assert_eq!(
+        document.offset_at(document.end_position()),
+        document.content_len(),
+        "The end position doesn't address the end of {content:?}"
+    );
+}
+
+/// Apply `count` random changes to the document, each one as its own
+/// [`FullTextDocument::update`] with the next version, and assert that the document
+/// stays consistent after each of them, see [`assert_consistent`]
+///
+/// The changes are generated from `seed`, the same seed and document always yield
+/// the same changes. They replace random ranges, including positions inside
+/// characters and beyond the end of lines, with text mixing `\n`, `\r\n` and `\r`
+/// line terminators and multi-byte characters, and occasionally replace the whole
+/// content. The applied changes are returned to reproduce a failure.
+///
+/// # Examples
+///
+/// Basic usage:
+/// ```
+/// use lsp_textdocument::testing::apply_random_edits;
+/// use lsp_textdocument::FullTextDocument;
+///
+/// let mut document = FullTextDocument::new("plain_text".to_string(), 1, "hello\nrust!".to_string());
+/// let changes = apply_random_edits(&mut document, 42, 100);
+/// assert_eq!(changes.len(), 100);
+/// assert_eq!(document.version(), 101);
+/// ```
+pub fn apply_random_edits(
+    document: &mut FullTextDocument,
+    seed: u64,
+    count: usize,
+) -> Vec<TextDocumentContentChangeEvent> {
+    let mut random = Random(seed);
+    let mut changes = Vec::with_capacity(count);
+    for _ in 0..count {
+        let change = random_change(document, &mut random);
+        document.update(std::slice::from_ref(&change), document.version() + 1);
+        assert_consistent(document);
+        changes.push(change);
+    }
+    changes
+}
+
+/// Decode arbitrary bytes, e.g. from a fuzzer, into change events
+///
+/// Unlike the changes of [`apply_random_edits`], these aren't necessarily valid:
+/// ranges may be inverted, positions may be far beyond the document, up to
+/// `u32::MAX`, and the text is any UTF-8 decoded from the bytes. Every byte
+/// sequence decodes to some changes.
+///
+/// # Examples
+///
+/// Basic usage:
+/// ```
+/// use lsp_textdocument::testing::{assert_consistent, changes_from_bytes};
+/// use lsp_textdocument::FullTextDocument;
+///
+/// let mut document = FullTextDocument::new("plain_text".to_string(), 0, "hello\nrust!".to_string());
+/// let changes = changes_from_bytes(b"\x02\xff\x01\x00\x03\x05\x03a\r\n");
+/// for (version, change) in changes.iter().enumerate() {
+///     // `try_update` never panics, whatever the change
+///     let _ = document.try_update(std::slice::from_ref(change), version as i32 + 1);
+///     assert_consistent(&document);
+/// }
+/// ```
+pub fn changes_from_bytes(data: &[u8]) -> Vec<TextDocumentContentChangeEvent> {
+    let mut bytes = Bytes(data);
+    let mut changes = Vec::new();
+    while let Some(kind) = bytes.next() {
+        let range = (kind % 8 != 0).then(|| {
+            let start = Position::new(bytes.number(), bytes.number());
+            Range::new(start, Position::new(bytes.number(), bytes.number()))
+        });
+        let len = bytes.next().unwrap_or(0) % 16;
+        let text: Vec<u8> = (0..len).map_while(|_| bytes.next()).collect();
+        changes.push(TextDocumentContentChangeEvent {
+            range,
+            range_length: None,
+            text: String::from_utf8_lossy(&text).into_owned(),
+        });
+    }
+    changes
+}
+
+/// The remaining bytes of [`changes_from_bytes`]
+struct Bytes<'a>(&'a [u8]);
+
+impl Bytes<'_> {
+    fn next(&mut self) -> Option<u8> {
+        let (&byte, rest) = self.0.split_first()?;
+        self.0 = rest;
+        Some(byte)
+    }
+
+    /// Mostly small numbers, which address the content, but also huge ones
+    fn number(&mut self) -> u32 {
+        match self.next().unwrap_or(0) {
+            byte @ 0..=0xf7 => u32::from(byte % 8),
+            0xf8..=0xfb => u32::MAX,
+            _ => u32::from_le_bytes([0; 4].map(|_| self.next().unwrap_or(0))),
+        }
+    }
+}
+
+fn random_change(
+    document: &FullTextDocument,
+    random: &mut Random,
+) -> TextDocumentContentChangeEvent {
+    let text = random.text(6);
+    if random.below(16) == 0 {
+        return TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text,
+        };
+    }
+
+    let mut random_position = || {
+        // one line past the end, and characters past the end of the line
+        let line = random.below(document.line_count() + 1);
+        let line_len = document
+            .line_with_terminator(line)
+            .map_or(0, |line| code_units(document, line));
+        let character = match random.below(8) {
+            0 => u32::MAX,
+            _ => random.below(line_len + 2),
+        };
+        Position::new(line, character)
+    };
+    let (start, end) = (random_position(), random_position());
+    let range = if edit_offset(document, start) <= edit_offset(document, end) {
+        Range::new(start, end)
+    } else {
+        Range::new(end, start)
+    };
+    TextDocumentContentChangeEvent {
+        range: Some(range),
+        range_length: None,
+        text,
+    }
+}
+
+/// The offset an edit at `position` applies to: a character beyond the end of the
+/// line is at the end of the line, before its terminator
+fn edit_offset(document: &FullTextDocument, position: Position) -> u32 {
+    match document.line_with_offset(position.line) {
+        Some((text, offset)) if position.character > code_units(document, text) => {
+            offset + text.len() as u32
+        }
+        _ => document.offset_at(position),
+    }
+}
+
+/// The length of `text` in code units of the document's position encoding
+fn code_units(document: &FullTextDocument, text: &str) -> u32 {
+    let encoding = document.position_encoding();
+    text.chars().map(|char| encoding.code_unit_len(char)).sum()
+}
+
+/// A xorshift generator, good enough to spread edits over a document
+pub(crate) struct Random(pub(crate) u64);
+
+impl Random {
+    /// A number in `0..bound`
+    pub(crate) fn below(&mut self, bound: u32) -> u32 {
+        // xorshift never leaves zero
+        let mut x = self.0.max(1);
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        (x >> 32) as u32 % bound
+    }
+
+    /// A text of less than `max_len` fragments, with every kind of line
+    /// terminator and multi-byte characters
+    pub(crate) fn text(&mut self, max_len: u32) -> String {
+        const FRAGMENTS: [&str; 8] = ["a", "bc", " ", "\n", "\r\n", "\r", "\u{20AC}", "\u{10437}"];
+
+        let len = self.below(max_len);
+        (0..len)
+            .map(|_| FRAGMENTS[self.below(FRAGMENTS.len() as u32) as usize])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PositionEncoding;
+
+    #[test]
+    fn test_changes_from_bytes() {
+        let mut random = Random(7);
+        for encoding in [
+            PositionEncoding::Utf8,
+            PositionEncoding::Utf16,
+            PositionEncoding::Utf32,
+        ] {
+            for _ in 0..500 {
+                let data: Vec<u8> = (0..random.below(64))
+                    .map(|_| random.below(256) as u8)
+                    .collect();
+                // the long line is indexed, see `ColumnIndex`
+                let content = format!("\u{10437}a\r\nb\rc\n{}", "\u{20AC}".repeat(100));
+                let mut document =
+                    FullTextDocument::with_encoding("js".to_string(), 0, content, encoding);
+                for (version, change) in changes_from_bytes(&data).iter().enumerate() {
+                    let _ = document.try_update(std::slice::from_ref(change), version as i32 + 1);
+                    assert_consistent(&document);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply_random_edits() {
+        for seed in 0..50 {
+            let mut document =
+                FullTextDocument::new("js".to_string(), 0, "\u{10437}a\r\nb\rc\n".to_string());
+            let changes = apply_random_edits(&mut document, seed, 200);
+
+            // the same seed yields the same changes
+            let mut replayed =
+                FullTextDocument::new("js".to_string(), 0, "\u{10437}a\r\nb\rc\n".to_string());
+            assert_eq!(apply_random_edits(&mut replayed, seed, 200), changes);
+            assert_eq!(replayed, document);
+        }
+    }
+}
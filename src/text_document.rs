@@ -1,594 +1,5575 @@
-use lsp_types::{Position, Range, TextDocumentContentChangeEvent};
+use crate::column_index::{ColumnIndex, MIN_INDEXED_LINE_LEN};
+use crate::line_len_cache::LineLenCache;
+use crate::{diff, Document, OffsetError, PositionEncoding, PositionError, UpdateError};
+use lsp_types::{Position, Range, TextDocumentContentChangeEvent, TextDocumentSyncKind, TextEdit};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::ops::RangeInclusive;
 
-#[derive(Debug)]
+/// The UTF-8 byte order mark some Windows editors prefix files with
+const BOM: char = '\u{FEFF}';
+
+/// The line terminator used by a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LineEnding {
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    CrLf,
+    /// A lone `\r`
+    Cr,
+    /// More than one kind of line terminator is used
+    Mixed,
+}
+
+/// The amount of each kind of line terminator in a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LineEndingCounts {
+    pub lf: u32,
+    pub crlf: u32,
+    pub cr: u32,
+}
+
+/// The whitespace a line is indented with, see [`FullTextDocument::line_indentation`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IndentKind {
+    /// The line isn't indented
+    None,
+    /// Only spaces
+    Spaces,
+    /// Only tabs
+    Tabs,
+    /// Both spaces and tabs
+    Mixed,
+}
+
+/// How [`FullTextDocument::try_update`] and
+/// [`TextDocuments::listen`](crate::TextDocuments::listen) handle changes whose
+/// version isn't greater than the current version of the document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StaleVersionPolicy {
+    /// Return [`UpdateError::StaleVersion`]
+    #[default]
+    Reject,
+    /// Ignore the changes
+    Skip,
+    /// Apply the changes and set the version anyway, like
+    /// [`FullTextDocument::update`]
+    Apply,
+}
+
+/// A text document, with the `serde` feature it can be serialized as a snapshot
+/// whose `line_offsets` are recomputed from the content on deserialization.
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "camelCase", try_from = "FullTextDocumentSnapshot")
+)]
 pub struct FullTextDocument {
     language_id: String,
     version: i32,
     content: String,
+    position_encoding: PositionEncoding,
+    sync_kind: Option<TextDocumentSyncKind>,
+    /// Whether the content was opened with a leading UTF-8 BOM, which is stripped
+    has_bom: bool,
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    stale_version_policy: StaleVersionPolicy,
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    history: History,
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    column_index: ColumnIndex,
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    line_len_cache: LineLenCache,
 
     /// The value at index `i` in `line_offsets` is the index into `content`
     /// that is the start of line `i`. As such, the first element of
     /// `line_offsets` is always 0.
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    line_offsets: Vec<u32>,
+}
+
+/// The serialized parts of a [`FullTextDocument`], anything derived from the
+/// content is not trusted from the payload. Content longer than
+/// [`FullTextDocument::max_supported_len`] is a deserialization error.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FullTextDocumentSnapshot {
+    language_id: String,
+    version: i32,
+    content: String,
+    #[serde(default)]
+    position_encoding: PositionEncoding,
+    #[serde(default)]
+    sync_kind: Option<TextDocumentSyncKind>,
+    #[serde(default)]
+    has_bom: bool,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<FullTextDocumentSnapshot> for FullTextDocument {
+    type Error = UpdateError;
+
+    fn try_from(snapshot: FullTextDocumentSnapshot) -> Result<Self, Self::Error> {
+        let mut document =
+            FullTextDocument::from_parts(snapshot.language_id, snapshot.version, snapshot.content)?;
+        document.position_encoding = snapshot.position_encoding;
+        document.sync_kind = snapshot.sync_kind;
+        document.has_bom = snapshot.has_bom;
+        Ok(document)
+    }
+}
+
+/// The parts of a [`FullTextDocument`] that an update changes
+#[derive(Debug, Clone)]
+struct DocumentState {
+    content: String,
     line_offsets: Vec<u32>,
+    version: i32,
+}
+
+/// The states of a [`FullTextDocument`] before its most recent updates, and the
+/// undone ones, keeping at most `depth` of each
+#[derive(Debug, Clone, Default)]
+struct History {
+    depth: usize,
+    undo: VecDeque<DocumentState>,
+    redo: Vec<DocumentState>,
+}
+
+impl History {
+    fn record(&mut self, state: DocumentState) {
+        if self.undo.len() == self.depth {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(state);
+        self.redo.clear();
+    }
+}
+
+/// A builder for a [`FullTextDocument`], see [`FullTextDocument::builder`]
+///
+/// Defaults to an empty `plaintext` document at version 0 whose positions are
+/// counted in UTF-16.
+#[derive(Debug, Clone)]
+pub struct FullTextDocumentBuilder {
+    language_id: String,
+    version: i32,
+    content: String,
+    position_encoding: PositionEncoding,
+}
+
+impl Default for FullTextDocumentBuilder {
+    fn default() -> Self {
+        Self {
+            language_id: "plaintext".to_string(),
+            version: 0,
+            content: String::new(),
+            position_encoding: PositionEncoding::default(),
+        }
+    }
+}
+
+impl FullTextDocumentBuilder {
+    /// Set the language id of the document
+    pub fn language_id(mut self, language_id: impl Into<String>) -> Self {
+        self.language_id = language_id.into();
+        self
+    }
+
+    /// Set the version of the document
+    pub fn version(mut self, version: i32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Set the content of the document, a leading UTF-8 BOM is stripped
+    pub fn content(mut self, content: impl Into<String>) -> Self {
+        self.content = content.into();
+        self
+    }
+
+    /// Set the encoding the positions of the document are counted in
+    pub fn encoding(mut self, position_encoding: PositionEncoding) -> Self {
+        self.position_encoding = position_encoding;
+        self
+    }
+
+    /// Create the document
+    pub fn build(self) -> FullTextDocument {
+        FullTextDocument::with_encoding(
+            self.language_id,
+            self.version,
+            self.content,
+            self.position_encoding,
+        )
+    }
+}
+
+/// An empty `plaintext` document, see [`FullTextDocument::empty`]
+impl Default for FullTextDocument {
+    fn default() -> Self {
+        Self::empty("plaintext".to_string())
+    }
+}
+
+/// Two documents are equal when their language id, version and content are equal,
+/// `line_offsets` are derived from the content and therefore not compared.
+impl PartialEq for FullTextDocument {
+    fn eq(&self, other: &Self) -> bool {
+        self.language_id == other.language_id
+            && self.version == other.version
+            && self.content == other.content
+    }
+}
+
+impl Eq for FullTextDocument {}
+
+impl Document for FullTextDocument {
+    fn new(language_id: String, version: i32, content: String) -> Self {
+        FullTextDocument::new(language_id, version, content)
+    }
+
+    fn with_encoding(
+        language_id: String,
+        version: i32,
+        content: String,
+        position_encoding: PositionEncoding,
+    ) -> Self {
+        FullTextDocument::with_encoding(language_id, version, content, position_encoding)
+    }
+
+    fn update(&mut self, changes: &[TextDocumentContentChangeEvent], version: i32) {
+        FullTextDocument::update(self, changes, version)
+    }
+
+    fn update_owned(&mut self, changes: Vec<TextDocumentContentChangeEvent>, version: i32) {
+        FullTextDocument::update_owned(self, changes, version)
+    }
+
+    fn try_update(
+        &mut self,
+        changes: &[TextDocumentContentChangeEvent],
+        version: i32,
+    ) -> Result<(), UpdateError> {
+        FullTextDocument::try_update(self, changes, version)
+    }
+
+    fn try_update_owned(
+        &mut self,
+        changes: Vec<TextDocumentContentChangeEvent>,
+        version: i32,
+    ) -> Result<(), UpdateError> {
+        FullTextDocument::try_update_owned(self, changes, version)
+    }
+
+    fn try_resync(&mut self, text: String) -> Result<(), UpdateError> {
+        // the version is kept, so it isn't stale
+        let change = TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text,
+        };
+        self.apply_owned_changes(vec![change], self.version)
+            .map(|_| ())
+    }
+
+    fn version(&self) -> i32 {
+        FullTextDocument::version(self)
+    }
+
+    fn get_content(&self, range: Option<Range>) -> &str {
+        FullTextDocument::get_content(self, range)
+    }
+
+    fn line_count(&self) -> u32 {
+        FullTextDocument::line_count(self)
+    }
+
+    fn position_at(&self, offset: u32) -> Position {
+        FullTextDocument::position_at(self, offset)
+    }
+
+    fn offset_at(&self, position: Position) -> u32 {
+        FullTextDocument::offset_at(self, position)
+    }
+
+    fn set_sync_kind(&mut self, sync_kind: TextDocumentSyncKind) {
+        FullTextDocument::set_sync_kind(self, sync_kind)
+    }
+
+    fn set_stale_version_policy(&mut self, stale_version_policy: StaleVersionPolicy) {
+        FullTextDocument::set_stale_version_policy(self, stale_version_policy)
+    }
 }
 
-fn computed_line_offsets(text: &str, is_at_line_start: bool, text_offset: Option<u32>) -> Vec<u32> {
+pub(crate) fn computed_line_offsets(
+    text: &str,
+    is_at_line_start: bool,
+    text_offset: Option<u32>,
+) -> Vec<u32> {
     let text_offset = text_offset.unwrap_or(0);
-    let mut line_offsets = if is_at_line_start {
-        vec![text_offset]
-    } else {
-        vec![]
-    };
-
-    let mut chars = text.char_indices().peekable();
-    while let Some((idx, char)) = chars.next() {
+    let bytes = text.as_bytes();
+    // Counting the `\n`s is much cheaper than growing the offsets of a large document
+    // one reallocation at a time, and exact unless lone `\r`s end lines. The count
+    // of a chunk fits in a `u8`, which lets the count vectorize.
+    let terminators: usize = bytes
+        .chunks(u8::MAX as usize)
+        .map(|chunk| {
+            let count: u8 = chunk.iter().map(|&byte| u8::from(byte == b'\n')).sum();
+            count as usize
+        })
+        .sum();
+    let mut line_offsets = Vec::with_capacity(terminators + usize::from(is_at_line_start));
+    if is_at_line_start {
+        line_offsets.push(text_offset);
+    }
+
+    // `\r` and `\n` are ASCII, so they never appear inside a multi-byte character
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let byte = bytes[idx];
+        idx += 1;
+        if byte == b'\r' && bytes.get(idx) == Some(&b'\n') {
+            idx += 1;
+        } else if byte != b'\n' && byte != b'\r' {
+            continue;
+        }
         let idx: u32 = idx
             .try_into()
             .expect("The length of the text involved in the calculation is too long");
-        if char == '\r' && chars.peek() == Some(&(idx as usize + 1, '\n')) {
-            chars.next();
-            line_offsets.push(text_offset + idx + 2);
-        } else if char == '\n' || char == '\r' {
-            line_offsets.push(text_offset + idx + 1);
-        }
+        line_offsets.push(text_offset + idx);
     }
 
     line_offsets
 }
 
-/// given a string (in UTF-8) and a byte offset, returns the offset in UTF-16 code units
+/// The line offsets of content that arrives in chunks, e.g. from a reader, which
+/// are the same as [`computed_line_offsets`] of the whole content. A chunk may end
+/// between the `\r` and the `\n` of a `\r\n`.
+#[derive(Debug)]
+struct LineOffsetsBuilder {
+    line_offsets: Vec<u32>,
+    len: u32,
+    /// Whether the last byte is a `\r`, whose line ends once the next byte is known
+    after_cr: bool,
+}
+
+impl LineOffsetsBuilder {
+    fn new() -> Self {
+        Self {
+            line_offsets: vec![0],
+            len: 0,
+            after_cr: false,
+        }
+    }
+
+    /// Scan the next chunk, the content must stay shorter than
+    /// [`FullTextDocument::max_supported_len`]
+    fn push(&mut self, chunk: &[u8]) {
+        for &byte in chunk {
+            let after_cr = std::mem::replace(&mut self.after_cr, false);
+            self.len += 1;
+            match byte {
+                b'\n' => self.line_offsets.push(self.len),
+                b'\r' => {
+                    if after_cr {
+                        self.line_offsets.push(self.len - 1);
+                    }
+                    self.after_cr = true;
+                }
+                _ if after_cr => self.line_offsets.push(self.len - 1),
+                _ => {}
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<u32> {
+        if self.after_cr {
+            self.line_offsets.push(self.len);
+        }
+        self.line_offsets
+    }
+}
+
+/// Whether content of `len` bytes is supported, see
+/// [`FullTextDocument::max_supported_len`]
+fn check_content_len(len: usize) -> Result<(), UpdateError> {
+    if len > FullTextDocument::max_supported_len() {
+        return Err(UpdateError::ContentTooLong { len });
+    }
+    Ok(())
+}
+
+/// The index of the last full text replacement of `changes`, which overrides every
+/// change before it, or 0
+fn last_full_text_change(changes: &[TextDocumentContentChangeEvent]) -> usize {
+    changes
+        .iter()
+        .rposition(|change| change.range.is_none())
+        .unwrap_or(0)
+}
+
+/// whether a line starts at byte `idx` of `content`, i.e. `idx` is the start of the
+/// content or right after a `\n`, `\r\n` or lone `\r` terminator
+fn is_line_start(content: &[u8], idx: usize) -> bool {
+    match idx.checked_sub(1).map(|prev| content[prev]) {
+        None => true,
+        Some(b'\n') => true,
+        Some(b'\r') => content.get(idx) != Some(&b'\n'),
+        Some(_) => false,
+    }
+}
+
+/// strips a single trailing line terminator (`\n`, `\r\n` or `\r`) from a line
+fn strip_line_terminator(line: &str) -> &str {
+    line.strip_suffix("\r\n")
+        .or_else(|| line.strip_suffix('\n'))
+        .or_else(|| line.strip_suffix('\r'))
+        .unwrap_or(line)
+}
+
+/// given a string (in UTF-8) and a byte offset, returns the offset in code units
+/// of the given `encoding`
 ///
 /// for example, consider a string containing a single 4-byte emoji. 4-byte characters
 /// in UTF-8 are supplementary plane characters that require two UTF-16 code units
 /// (surrogate pairs).
 ///
-/// in this example:
+/// in this example, with UTF-16:
 /// - offset 4 returns 2;
 /// - offsets 1, 2 or 3 return 0, because they are not on a character boundary and round down;
 /// - offset 5+ will return 2, the length of the string in UTF-16
-fn line_offset_utf16(line: &str, offset: u32) -> u32 {
+fn line_offset_code_units(line: &str, offset: u32, encoding: PositionEncoding) -> u32 {
     let mut c = 0;
     for (idx, char) in line.char_indices() {
         if idx + char.len_utf8() > offset as usize || idx == offset as usize {
             break;
         }
-        c += char.len_utf16() as u32;
+        c += encoding.code_unit_len(char);
     }
     c
 }
 
 impl FullTextDocument {
+    /// Create a document
+    ///
+    /// A leading UTF-8 BOM (`\u{FEFF}`) is stripped from the content, so that it
+    /// doesn't offset the positions of the first line, see
+    /// [`FullTextDocument::has_bom`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the content is longer than [`FullTextDocument::max_supported_len`],
+    /// see [`FullTextDocument::try_new`] for a non-panicking alternative.
     pub fn new(language_id: String, version: i32, content: String) -> Self {
-        let line_offsets = computed_line_offsets(&content, true, None);
-        Self {
-            language_id,
-            version,
-            content,
-            line_offsets,
-        }
+        Self::with_encoding(language_id, version, content, PositionEncoding::default())
     }
 
-    pub fn update(&mut self, changes: &[TextDocumentContentChangeEvent], version: i32) {
-        for change in changes {
-            let TextDocumentContentChangeEvent { range, text, .. } = change;
-            match range {
-                Some(range) => {
-                    // update content
-                    let Range { start, end } = range;
-                    let (start, start_offset) = self.find_canonical_position(start);
-                    let (end, end_offset) = self.find_canonical_position(end);
-                    assert!(
-                        start_offset <= end_offset,
-                        "Start offset must be less than end offset. {}:{} (offset {}) is not <= {}:{} (offset {})",
-                        start.line, start.character, start_offset,
-                        end.line, end.character, end_offset
-                    );
-                    self.content
-                        .replace_range((start_offset as usize)..(end_offset as usize), text);
-
-                    let (start_line, end_line) = (start.line, end.line);
-                    assert!(start_line <= end_line);
-                    let added_line_offsets = computed_line_offsets(text, false, Some(start_offset));
-                    let num_added_line_offsets = added_line_offsets.len();
-
-                    let splice_start = start_line as usize + 1;
-                    self.line_offsets
-                        .splice(splice_start..=end_line as usize, added_line_offsets);
-
-                    let diff =
-                        (text.len() as i32).saturating_sub_unsigned(end_offset - start_offset);
-                    if diff != 0 {
-                        for i in
-                            (splice_start + num_added_line_offsets)..(self.line_count() as usize)
-                        {
-                            self.line_offsets[i] = self.line_offsets[i].saturating_add_signed(diff);
-                        }
-                    }
-                }
-                None => {
-                    // Full Text
-                    // update line_offsets
-                    self.line_offsets = computed_line_offsets(text, true, None);
+    /// Create a document, returning [`UpdateError::ContentTooLong`] instead of
+    /// panicking if the content is longer than
+    /// [`FullTextDocument::max_supported_len`]
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    ///
+    /// let document = FullTextDocument::try_new("plain_text".to_string(), 1, "hello rust!".to_string());
+    /// assert!(document.is_ok());
+    /// ```
+    pub fn try_new(
+        language_id: String,
+        version: i32,
+        content: String,
+    ) -> Result<Self, UpdateError> {
+        check_content_len(content.len())?;
+        Ok(Self::new(language_id, version, content))
+    }
 
-                    // update content
-                    self.content = text.to_owned();
-                }
+    /// Create a document from the content of a reader, e.g. a file the client
+    /// hasn't opened
+    ///
+    /// A leading UTF-8 BOM is stripped like in [`FullTextDocument::new`]. Content that
+    /// isn't UTF-8 or is longer than [`FullTextDocument::max_supported_len`] is an
+    /// [`io::ErrorKind::InvalidData`] error.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    ///
+    /// let file: &[u8] = b"hello\nrust!";
+    /// let document = FullTextDocument::from_reader("plain_text".to_string(), 1, file).unwrap();
+    /// assert_eq!(document.get_content(None), "hello\nrust!");
+    /// assert_eq!(document.line_count(), 2);
+    /// ```
+    pub fn from_reader(
+        language_id: String,
+        version: i32,
+        mut reader: impl io::Read,
+    ) -> io::Result<Self> {
+        const CHUNK_LEN: u64 = 64 * 1024;
+
+        // the line offsets are computed as the content is read, which saves scanning
+        // a large content a second time
+        let mut bytes = Vec::new();
+        let mut line_offsets = LineOffsetsBuilder::new();
+        let mut bom = [0; 4];
+        let bom = BOM.encode_utf8(&mut bom).as_bytes();
+        // whether the content starts with a BOM, once enough of it is read
+        let mut has_bom = None;
+        let mut scanned = 0;
+        loop {
+            let read = reader.by_ref().take(CHUNK_LEN).read_to_end(&mut bytes)?;
+            if has_bom.is_none() && (bytes.len() >= bom.len() || read == 0) {
+                let starts_with_bom = bytes.starts_with(bom);
+                scanned = if starts_with_bom { bom.len() } else { 0 };
+                has_bom = Some(starts_with_bom);
+            }
+            if let Some(has_bom) = has_bom {
+                let len = bytes.len() - if has_bom { bom.len() } else { 0 };
+                check_content_len(len)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                line_offsets.push(&bytes[scanned..]);
+                scanned = bytes.len();
+            }
+            if read == 0 {
+                break;
             }
         }
 
-        self.version = version;
+        let has_bom = has_bom.unwrap_or(false);
+        let mut content = String::from_utf8(bytes)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if has_bom {
+            content.drain(..BOM.len_utf8());
+        }
+        let line_offsets = line_offsets.finish();
+        Ok(Self::from_line_offsets(
+            language_id,
+            version,
+            content,
+            line_offsets,
+            has_bom,
+        ))
     }
 
-    /// As demonstrated by test_multiple_position_same_offset(), in some cases,
-    /// there are multiple ways to reference the same Position. We map to a
-    /// "canonical Position" so we can avoid worrying about edge cases all over
-    /// the place.
-    fn find_canonical_position(&self, position: &Position) -> (Position, u32) {
-        let offset = self.offset_at(*position);
-        if offset == 0 {
-            (
-                Position {
-                    line: 0,
-                    character: 0,
-                },
-                0,
-            )
-        } else if self.content.as_bytes().get(offset as usize - 1) == Some(&b'\n') {
-            if self.line_offsets[position.line as usize] == offset {
-                (*position, offset)
-            } else if self.line_offsets[position.line as usize + 1] == offset {
-                (
-                    Position {
-                        line: position.line + 1,
-                        character: 0,
-                    },
-                    offset,
-                )
-            } else {
-                panic!(
-                    "Could not determine canonical value for {position:?} in {:?}",
-                    self.content
-                )
-            }
-        } else {
-            (*position, offset)
+    /// The length in bytes of the longest content a document supports
+    ///
+    /// Offsets and line numbers are `u32`, as in the LSP specification, so the
+    /// content of a document is limited to a little less than 4 GiB. Changes that
+    /// would make the content longer are rejected with
+    /// [`UpdateError::ContentTooLong`].
+    pub const fn max_supported_len() -> usize {
+        // every line but the last one takes a byte, so the line count fits too
+        u32::MAX as usize - 1
+    }
+
+    /// Create a document whose positions are counted in the given `encoding`
+    ///
+    /// A leading UTF-8 BOM is stripped like in [`FullTextDocument::new`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the content is longer than [`FullTextDocument::max_supported_len`].
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::{FullTextDocument, PositionEncoding};
+    /// use lsp_types::Position;
+    ///
+    /// let document = FullTextDocument::with_encoding(
+    ///     "plain_text".to_string(),
+    ///     1,
+    ///     "\u{20AC} euro".to_string(),
+    ///     PositionEncoding::Utf8,
+    /// );
+    /// assert_eq!(document.position_encoding(), PositionEncoding::Utf8);
+    /// assert_eq!(document.offset_at(Position::new(0, 4)), 4);
+    /// ```
+    pub fn with_encoding(
+        language_id: String,
+        version: i32,
+        mut content: String,
+        position_encoding: PositionEncoding,
+    ) -> Self {
+        let has_bom = content.starts_with(BOM);
+        if has_bom {
+            content.drain(..BOM.len_utf8());
+        }
+        if let Err(err) = check_content_len(content.len()) {
+            panic!("{err}");
+        }
+        let line_offsets = computed_line_offsets(&content, true, None);
+        Self {
+            position_encoding,
+            ..Self::from_line_offsets(language_id, version, content, line_offsets, has_bom)
         }
     }
 
-    /// Document's language id
-    pub fn language_id(&self) -> &str {
-        &self.language_id
+    /// Create a document from the parts of another one, e.g. a persisted document,
+    /// whose line offsets are recomputed from the content
+    ///
+    /// Unlike [`FullTextDocument::new`], the content is kept as is, a leading
+    /// `\u{FEFF}` isn't stripped. Returns [`UpdateError::ContentTooLong`] if the
+    /// content is longer than [`FullTextDocument::max_supported_len`].
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    ///
+    /// let document = FullTextDocument::from_parts("plain_text".to_string(), 1, "\u{FEFF}hello\nrust!".to_string()).unwrap();
+    /// assert_eq!(document.get_content(None), "\u{FEFF}hello\nrust!");
+    /// assert_eq!(document.line_count(), 2);
+    /// assert!(!document.has_bom());
+    /// ```
+    pub fn from_parts(
+        language_id: String,
+        version: i32,
+        content: String,
+    ) -> Result<Self, UpdateError> {
+        check_content_len(content.len())?;
+        let line_offsets = computed_line_offsets(&content, true, None);
+        Ok(Self::from_line_offsets(
+            language_id,
+            version,
+            content,
+            line_offsets,
+            false,
+        ))
     }
 
-    /// Document's version
-    pub fn version(&self) -> i32 {
-        self.version
+    /// A document with the default options, whose `line_offsets` are those of the
+    /// content
+    fn from_line_offsets(
+        language_id: String,
+        version: i32,
+        content: String,
+        line_offsets: Vec<u32>,
+        has_bom: bool,
+    ) -> Self {
+        Self {
+            language_id,
+            version,
+            content,
+            position_encoding: PositionEncoding::default(),
+            sync_kind: None,
+            has_bom,
+            stale_version_policy: StaleVersionPolicy::default(),
+            history: History::default(),
+            column_index: ColumnIndex::default(),
+            line_len_cache: LineLenCache::default(),
+            line_offsets,
+        }
     }
 
-    /// Get document content
+    /// Create an empty document at version 0, which like an empty buffer in an
+    /// editor has a single empty line
     ///
     /// # Examples
     ///
     /// Basic usage:
     /// ```
     /// use lsp_textdocument::FullTextDocument;
-    /// use lsp_types::{Range, Position};
     ///
-    /// let text_documents = FullTextDocument::new("plain_text".to_string(), 1, "hello rust!".to_string());
+    /// let document = FullTextDocument::empty("rust".to_string());
+    /// assert_eq!(document.get_content(None), "");
+    /// assert_eq!(document.version(), 0);
+    /// assert_eq!(document.line_count(), 1);
+    /// ```
+    pub fn empty(language_id: String) -> Self {
+        Self::new(language_id, 0, String::new())
+    }
+
+    /// Create a document with a builder, which is convenient when only some of the
+    /// options matter
     ///
-    /// // get document all content
-    /// let content = text_documents.get_content(None);
-    /// assert_eq!(content, "hello rust!");
+    /// # Examples
     ///
-    /// // get document specify content by range
-    /// let (start, end) = (Position::new(0, 1), Position::new(0, 9));
-    /// let range = Range::new(start, end);
-    /// let sub_content = text_documents.get_content(Some(range));
-    /// assert_eq!(sub_content, "ello rus");
+    /// Basic usage:
     /// ```
-    pub fn get_content(&self, range: Option<Range>) -> &str {
-        match range {
-            Some(Range { start, end }) => {
-                let start = self.offset_at(start);
-                let end = self.offset_at(end).min(self.content_len());
-                self.content.get(start as usize..end as usize).unwrap()
-            }
-            None => &self.content,
-        }
+    /// use lsp_textdocument::{FullTextDocument, PositionEncoding};
+    ///
+    /// let document = FullTextDocument::builder()
+    ///     .content("hello rust!")
+    ///     .encoding(PositionEncoding::Utf8)
+    ///     .build();
+    /// assert_eq!(document.get_content(None), "hello rust!");
+    /// assert_eq!(document.version(), 0);
+    /// assert_eq!(document.position_encoding(), PositionEncoding::Utf8);
+    /// ```
+    pub fn builder() -> FullTextDocumentBuilder {
+        FullTextDocumentBuilder::default()
     }
 
-    fn get_line_and_offset(&self, line: u32) -> Option<(&str, u32)> {
-        self.line_offsets.get(line as usize).map(|&line_offset| {
-            let len: u32 = self.content_len();
-            let eol_offset = self.line_offsets.get((line + 1) as usize).unwrap_or(&len);
-            let line = &self.content[line_offset as usize..*eol_offset as usize];
-            (line, line_offset)
+    /// Apply the changes to the document and set its version
+    ///
+    /// Empty `changes`, which some clients send as a keepalive, only set the version
+    /// and leave the content and line offsets untouched.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a change can not be applied, see [`FullTextDocument::try_update`]
+    /// for a non-panicking alternative. Unlike `try_update`, the version is not
+    /// checked, the changes of a stale version are applied as well.
+    pub fn update(&mut self, changes: &[TextDocumentContentChangeEvent], version: i32) {
+        if let Err(err) = self.apply_changes(changes, version) {
+            panic!("{err}");
+        }
+    }
+
+    /// Apply the changes to the document and set its version like
+    /// [`FullTextDocument::update`], taking ownership of the changes
+    ///
+    /// The text of a full text replacement becomes the content as is, which saves
+    /// copying the whole document, e.g. for the changes of a deserialized
+    /// `textDocument/didChange` notification.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a change can not be applied, like [`FullTextDocument::update`], and
+    /// doesn't check the version either.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::TextDocumentContentChangeEvent;
+    ///
+    /// let mut document = FullTextDocument::new("plain_text".to_string(), 1, "hello rust!".to_string());
+    /// let change = TextDocumentContentChangeEvent {
+    ///     range: None,
+    ///     range_length: None,
+    ///     text: "hello lsp!".to_string(),
+    /// };
+    /// document.update_owned(vec![change], 2);
+    /// assert_eq!(document.get_content(None), "hello lsp!");
+    /// assert_eq!(document.version(), 2);
+    /// ```
+    pub fn update_owned(&mut self, changes: Vec<TextDocumentContentChangeEvent>, version: i32) {
+        if let Err(err) = self.apply_owned_changes(changes, version) {
+            panic!("{err}");
+        }
+    }
+
+    /// Apply the changes to the document and set its version like
+    /// [`FullTextDocument::update`], returning the lines of the updated document that
+    /// were added or modified
+    ///
+    /// The lines after the returned ones are unchanged, but shifted if lines were
+    /// added or removed. Removed lines are reported as the line they were joined
+    /// into. The range is empty if there are no `changes`, and covers the whole
+    /// document for a full text replacement.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a change can not be applied, like [`FullTextDocument::update`].
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Position, Range, TextDocumentContentChangeEvent};
+    ///
+    /// let mut document = FullTextDocument::new("plain_text".to_string(), 1, "a\nb\nc\nd".to_string());
+    /// let change = TextDocumentContentChangeEvent {
+    ///     range: Some(Range::new(Position::new(1, 0), Position::new(2, 1))),
+    ///     range_length: None,
+    ///     text: "x".to_string(),
+    /// };
+    /// assert_eq!(document.update_reporting(&[change], 2), 1..=1);
+    /// assert_eq!(document.get_content(None), "a\nx\nd");
+    /// ```
+    pub fn update_reporting(
+        &mut self,
+        changes: &[TextDocumentContentChangeEvent],
+        version: i32,
+    ) -> RangeInclusive<u32> {
+        match self.apply_changes(changes, version) {
+            Ok(lines) => lines,
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    /// Apply the changes to the document and set its version, returning an error
+    /// instead of panicking when a change can not be applied
+    ///
+    /// Changes are validated one at a time against the current content, so the
+    /// changes before the failing one stay applied. A server would typically
+    /// request a full resync of the document on error. Changes before the last full
+    /// replacement are skipped without being validated.
+    ///
+    /// A `version` that isn't greater than the current one is stale, it is handled
+    /// according to the document's [`StaleVersionPolicy`].
+    ///
+    /// It never panics, whatever the changes: positions beyond the document are
+    /// clamped to it and inverted ranges are rejected. See the `try_update` fuzz
+    /// target in `fuzz/`.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::{FullTextDocument, UpdateError};
+    /// use lsp_types::{Position, Range, TextDocumentContentChangeEvent};
+    ///
+    /// let mut document = FullTextDocument::new("plain_text".to_string(), 1, "hello\nrust!".to_string());
+    /// let change = TextDocumentContentChangeEvent {
+    ///     range: Some(Range::new(Position::new(1, 0), Position::new(0, 0))),
+    ///     range_length: None,
+    ///     text: String::new(),
+    /// };
+    /// let result = document.try_update(&[change], 2);
+    /// assert!(matches!(result, Err(UpdateError::InvertedRange { .. })));
+    /// ```
+    pub fn try_update(
+        &mut self,
+        changes: &[TextDocumentContentChangeEvent],
+        version: i32,
+    ) -> Result<(), UpdateError> {
+        if !self.accepts_version(version)? {
+            return Ok(());
+        }
+        self.apply_changes(changes, version).map(|_| ())
+    }
+
+    /// Apply the changes to the document and set its version like
+    /// [`FullTextDocument::try_update`], taking ownership of the changes like
+    /// [`FullTextDocument::update_owned`]
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::{FullTextDocument, UpdateError};
+    /// use lsp_types::TextDocumentContentChangeEvent;
+    ///
+    /// let mut document = FullTextDocument::new("plain_text".to_string(), 2, "hello rust!".to_string());
+    /// let change = TextDocumentContentChangeEvent {
+    ///     range: None,
+    ///     range_length: None,
+    ///     text: "hello lsp!".to_string(),
+    /// };
+    /// let result = document.try_update_owned(vec![change], 1);
+    /// assert_eq!(result, Err(UpdateError::StaleVersion { current: 2, received: 1 }));
+    /// ```
+    pub fn try_update_owned(
+        &mut self,
+        changes: Vec<TextDocumentContentChangeEvent>,
+        version: i32,
+    ) -> Result<(), UpdateError> {
+        if !self.accepts_version(version)? {
+            return Ok(());
+        }
+        self.apply_owned_changes(changes, version).map(|_| ())
+    }
+
+    /// Whether changes of `version` should be applied, a stale version is handled
+    /// according to the [`StaleVersionPolicy`]
+    fn accepts_version(&self, version: i32) -> Result<bool, UpdateError> {
+        if version > self.version {
+            return Ok(true);
+        }
+        match self.stale_version_policy {
+            StaleVersionPolicy::Reject => Err(UpdateError::StaleVersion {
+                current: self.version,
+                received: version,
+            }),
+            StaleVersionPolicy::Skip => Ok(false),
+            StaleVersionPolicy::Apply => Ok(true),
+        }
+    }
+
+    /// The version the next change is expected to have, a change with a greater
+    /// version means that changes were missed
+    pub fn expects_version(&self) -> i32 {
+        self.version.saturating_add(1)
+    }
+
+    /// Configure how [`FullTextDocument::try_update`] handles stale versions
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::{FullTextDocument, StaleVersionPolicy};
+    /// use lsp_types::TextDocumentContentChangeEvent;
+    ///
+    /// let mut document = FullTextDocument::new("plain_text".to_string(), 2, "hello rust!".to_string());
+    /// document.set_stale_version_policy(StaleVersionPolicy::Skip);
+    ///
+    /// let change = TextDocumentContentChangeEvent {
+    ///     range: None,
+    ///     range_length: None,
+    ///     text: "out of order".to_string(),
+    /// };
+    /// assert_eq!(document.try_update(&[change], 1), Ok(()));
+    /// assert_eq!(document.get_content(None), "hello rust!");
+    /// assert_eq!(document.version(), 2);
+    /// ```
+    pub fn set_stale_version_policy(&mut self, stale_version_policy: StaleVersionPolicy) {
+        self.stale_version_policy = stale_version_policy;
+    }
+
+    /// Apply the changes and set the version, returning the lines of the updated
+    /// document that were added or modified
+    fn apply_changes(
+        &mut self,
+        changes: &[TextDocumentContentChangeEvent],
+        version: i32,
+    ) -> Result<RangeInclusive<u32>, UpdateError> {
+        let changes = &changes[last_full_text_change(changes)..];
+        let changes = changes
+            .iter()
+            .map(|change| (change.range, Cow::Borrowed(change.text.as_str())));
+        self.apply_change_texts(changes, version)
+    }
+
+    /// [`FullTextDocument::apply_changes`] of owned changes, whose full text
+    /// replacements are moved into the content
+    fn apply_owned_changes(
+        &mut self,
+        mut changes: Vec<TextDocumentContentChangeEvent>,
+        version: i32,
+    ) -> Result<RangeInclusive<u32>, UpdateError> {
+        let first = last_full_text_change(&changes);
+        let changes = changes
+            .drain(first..)
+            .map(|change| (change.range, Cow::Owned(change.text)));
+        self.apply_change_texts(changes, version)
+    }
+
+    fn apply_change_texts<'a>(
+        &mut self,
+        mut changes: impl Iterator<Item = (Option<Range>, Cow<'a, str>)>,
+        version: i32,
+    ) -> Result<RangeInclusive<u32>, UpdateError> {
+        let state = (self.history.depth > 0).then(|| self.state());
+
+        let mut lines: Option<(u32, u32)> = None;
+        let result = changes.try_for_each(|(range, text)| {
+            let removed_end = match range {
+                Some(range) => self.line_at(self.edit_offset(&range.end)),
+                None => self.line_count() - 1,
+            };
+            let Range { start, end } = self.apply_change_text(range.as_ref(), text)?;
+            lines = Some(match lines {
+                Some((first_line, last_line)) => {
+                    // lines changed before are shifted by the lines this change added,
+                    // or replaced along with the lines it removed
+                    let last_line = if last_line > removed_end {
+                        last_line - removed_end + end.line
+                    } else {
+                        end.line
+                    };
+                    (first_line.min(start.line), last_line)
+                }
+                None => (start.line, end.line),
+            });
+            Ok(())
+        });
+
+        if let Some(state) = state {
+            // changes before a failing one stay applied and can be undone
+            if result.is_ok() || state.content != self.content {
+                self.history.record(state);
+            }
+        }
+        result?;
+
+        self.version = version;
+        Ok(match lines {
+            Some((first_line, last_line)) => first_line..=last_line,
+            None => RangeInclusive::new(1, 0),
         })
     }
 
-    fn get_line(&self, line: u32) -> Option<&str> {
-        self.get_line_and_offset(line).map(|(line, _)| line)
+    fn state(&self) -> DocumentState {
+        DocumentState {
+            content: self.content.clone(),
+            line_offsets: self.line_offsets.clone(),
+            version: self.version,
+        }
+    }
+
+    fn restore(&mut self, state: DocumentState) -> DocumentState {
+        self.column_index.clear();
+        self.line_len_cache.reset(state.line_offsets.len());
+        DocumentState {
+            content: std::mem::replace(&mut self.content, state.content),
+            line_offsets: std::mem::replace(&mut self.line_offsets, state.line_offsets),
+            version: std::mem::replace(&mut self.version, state.version),
+        }
+    }
+
+    /// Keep the states before the last `depth` updates, so that they can be
+    /// reverted with [`FullTextDocument::undo`]
+    ///
+    /// The history is disabled by default, each kept state is a copy of the
+    /// content. Only [`FullTextDocument::update`], its variants and
+    /// [`FullTextDocument::try_update`] are recorded.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::TextDocumentContentChangeEvent;
+    ///
+    /// let mut document = FullTextDocument::new("plain_text".to_string(), 1, "hello rust!".to_string());
+    /// document.set_history_depth(10);
+    ///
+    /// let change = TextDocumentContentChangeEvent {
+    ///     range: None,
+    ///     range_length: None,
+    ///     text: "formatted".to_string(),
+    /// };
+    /// document.update(&[change], 2);
+    ///
+    /// assert!(document.undo());
+    /// assert_eq!(document.get_content(None), "hello rust!");
+    /// assert_eq!(document.version(), 1);
+    /// assert!(document.redo());
+    /// assert_eq!(document.get_content(None), "formatted");
+    /// ```
+    pub fn set_history_depth(&mut self, depth: usize) {
+        let history = &mut self.history;
+        history.depth = depth;
+        let excess = history.undo.len().saturating_sub(depth);
+        history.undo.drain(..excess);
+        history.redo.truncate(depth);
+    }
+
+    /// Revert the last recorded update, returns `false` if there is none
+    ///
+    /// See [`FullTextDocument::set_history_depth`].
+    pub fn undo(&mut self) -> bool {
+        match self.history.undo.pop_back() {
+            Some(state) => {
+                let state = self.restore(state);
+                self.history.redo.push(state);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reapply the last undone update, returns `false` if there is none
+    ///
+    /// Any update after an undo discards the undone updates.
+    pub fn redo(&mut self) -> bool {
+        match self.history.redo.pop() {
+            Some(state) => {
+                let state = self.restore(state);
+                self.history.undo.push_back(state);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Release the capacity the content, the line offsets and the recorded history
+    /// hold beyond their length, e.g. after most of a large document was deleted
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::TextDocumentContentChangeEvent;
+    ///
+    /// let content = "hello rust!\n".repeat(1000);
+    /// let mut document = FullTextDocument::new("plain_text".to_string(), 1, content);
+    /// let change = TextDocumentContentChangeEvent {
+    ///     range: None,
+    ///     range_length: None,
+    ///     text: "bye".to_string(),
+    /// };
+    /// document.update(&[change], 2);
+    ///
+    /// document.shrink_to_fit();
+    /// assert_eq!(document.get_content(None), "bye");
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        self.content.shrink_to_fit();
+        self.line_offsets.shrink_to_fit();
+        self.column_index.clear();
+        let history = &mut self.history;
+        for state in history.undo.iter_mut().chain(history.redo.iter_mut()) {
+            state.content.shrink_to_fit();
+            state.line_offsets.shrink_to_fit();
+        }
+        history.undo.shrink_to_fit();
+        history.redo.shrink_to_fit();
+    }
+
+    /// Apply a single change without touching the version, returning the range the
+    /// inserted text occupies in the updated document
+    ///
+    /// For a full text replacement the range spans the whole document.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Position, Range, TextDocumentContentChangeEvent};
+    ///
+    /// let mut document = FullTextDocument::new("plain_text".to_string(), 1, "hello rust!".to_string());
+    /// let change = TextDocumentContentChangeEvent {
+    ///     range: Some(Range::new(Position::new(0, 6), Position::new(0, 10))),
+    ///     range_length: None,
+    ///     text: "lsp\nserver".to_string(),
+    /// };
+    /// let range = document.apply_change(&change).unwrap();
+    /// assert_eq!(range, Range::new(Position::new(0, 6), Position::new(1, 6)));
+    /// assert_eq!(document.get_content(None), "hello lsp\nserver!");
+    /// ```
+    pub fn apply_change(
+        &mut self,
+        change: &TextDocumentContentChangeEvent,
+    ) -> Result<Range, UpdateError> {
+        self.apply_change_text(change.range.as_ref(), Cow::Borrowed(&change.text))
+    }
+
+    /// [`FullTextDocument::apply_change`] of the `range` and `text` of a change, the
+    /// text of a full text replacement becomes the content without a copy if owned
+    fn apply_change_text(
+        &mut self,
+        range: Option<&Range>,
+        text: Cow<'_, str>,
+    ) -> Result<Range, UpdateError> {
+        if let (Some(range), Some(TextDocumentSyncKind::FULL)) = (range, self.sync_kind) {
+            return Err(UpdateError::UnexpectedIncrementalChange { range: *range });
+        }
+        match range {
+            Some(range) => self.replace_range(range, &text),
+            None => {
+                // Full Text
+                // clients may re-send the content verbatim, e.g. on save, comparing is
+                // cheaper than reallocating the content and rescanning the line offsets
+                if *text != self.content {
+                    check_content_len(text.len())?;
+                    self.column_index.clear();
+
+                    // update line_offsets
+                    self.line_offsets = computed_line_offsets(&text, true, None);
+                    self.line_len_cache.reset(self.line_offsets.len());
+
+                    // update content
+                    self.content = text.into_owned();
+                }
+
+                Ok(Range {
+                    start: Position {
+                        line: 0,
+                        character: 0,
+                    },
+                    end: self.end_position(),
+                })
+            }
+        }
+    }
+
+    /// Replace the text in `range` with `replacement` without touching the version,
+    /// returning the end of the inserted text in the updated document
+    ///
+    /// This is a single incremental change of [`FullTextDocument::update`], e.g. to
+    /// build up a document from edits that aren't tied to an LSP version.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Position, Range};
+    ///
+    /// let mut document = FullTextDocument::new("plain_text".to_string(), 1, "hello rust!".to_string());
+    /// let range = Range::new(Position::new(0, 6), Position::new(0, 10));
+    /// let end = document.splice(range, "lsp\nserver").unwrap();
+    /// assert_eq!(end, Position::new(1, 6));
+    /// assert_eq!(document.get_content(None), "hello lsp\nserver!");
+    /// assert_eq!(document.version(), 1);
+    /// ```
+    pub fn splice(&mut self, range: Range, replacement: &str) -> Result<Position, UpdateError> {
+        self.replace_range(&range, replacement)
+            .map(|range| range.end)
+    }
+
+    /// Replace the text in `range` with `text`, returning the range the inserted text
+    /// occupies in the updated document
+    fn replace_range(&mut self, range: &Range, text: &str) -> Result<Range, UpdateError> {
+        let Range { start, end } = range;
+        let (start, start_offset) = self.find_canonical_position(start);
+        let (end, end_offset) = self.find_canonical_position(end);
+        if start_offset > end_offset {
+            return Err(UpdateError::InvertedRange {
+                start,
+                start_offset,
+                end,
+                end_offset,
+            });
+        }
+        check_content_len(self.content.len() - (end_offset - start_offset) as usize + text.len())?;
+        Ok(self.replace_offsets(start_offset, end_offset, text))
+    }
+
+    /// Apply edits authored by the server, e.g. from a [`lsp_types::WorkspaceEdit`],
+    /// without touching the version
+    ///
+    /// Like for a `WorkspaceEdit`, all ranges refer to the current content and
+    /// inserts at the same position are inserted in the order of `edits`. The edits
+    /// are validated before any of them is applied, overlapping edits are rejected
+    /// with [`UpdateError::OverlappingEdits`].
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Position, Range, TextEdit};
+    ///
+    /// let mut document = FullTextDocument::new("plain_text".to_string(), 1, "hello rust!".to_string());
+    /// let edits = [
+    ///     TextEdit::new(Range::new(Position::new(0, 0), Position::new(0, 5)), "hi".to_string()),
+    ///     TextEdit::new(Range::new(Position::new(0, 6), Position::new(0, 10)), "lsp".to_string()),
+    /// ];
+    /// document.apply_text_edits(&edits).unwrap();
+    /// assert_eq!(document.get_content(None), "hi lsp!");
+    /// ```
+    pub fn apply_text_edits(&mut self, edits: &[TextEdit]) -> Result<(), UpdateError> {
+        let edits: Vec<(Range, &str)> = edits
+            .iter()
+            .map(|edit| (edit.range, edit.new_text.as_str()))
+            .collect();
+        self.apply_disjoint_edits(&edits)
+    }
+
+    /// Apply changes that don't overlap, e.g. made with multiple cursors, at once
+    /// without touching the version
+    ///
+    /// Unlike for [`FullTextDocument::update`], all ranges refer to the current
+    /// content, so the changes don't need to be sorted or adjusted for each other.
+    /// The changes are validated before any of them is applied, a change without a
+    /// range covers the whole document. See [`FullTextDocument::apply_text_edits`].
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Position, Range, TextDocumentContentChangeEvent};
+    ///
+    /// let mut document = FullTextDocument::new("plain_text".to_string(), 1, "let a;\nlet b;".to_string());
+    /// let insert_mut = |line| TextDocumentContentChangeEvent {
+    ///     range: Some(Range::new(Position::new(line, 4), Position::new(line, 4))),
+    ///     range_length: None,
+    ///     text: "mut ".to_string(),
+    /// };
+    /// document.apply_disjoint_changes(&[insert_mut(1), insert_mut(0)]).unwrap();
+    /// assert_eq!(document.get_content(None), "let mut a;\nlet mut b;");
+    /// ```
+    pub fn apply_disjoint_changes(
+        &mut self,
+        changes: &[TextDocumentContentChangeEvent],
+    ) -> Result<(), UpdateError> {
+        let whole_document = Range::new(Position::new(0, 0), self.end_position());
+        let mut edits = Vec::with_capacity(changes.len());
+        for change in changes {
+            let range = match (change.range, self.sync_kind) {
+                (Some(range), Some(TextDocumentSyncKind::FULL)) => {
+                    return Err(UpdateError::UnexpectedIncrementalChange { range });
+                }
+                (Some(range), _) => range,
+                (None, _) => whole_document,
+            };
+            edits.push((range, change.text.as_str()));
+        }
+        self.apply_disjoint_edits(&edits)
+    }
+
+    /// Replace the ranges, which refer to the current content, with their text
+    fn apply_disjoint_edits(&mut self, edits: &[(Range, &str)]) -> Result<(), UpdateError> {
+        let mut offsets = Vec::with_capacity(edits.len());
+        for (idx, (range, _)) in edits.iter().enumerate() {
+            let (start, start_offset) = self.find_canonical_position(&range.start);
+            let (end, end_offset) = self.find_canonical_position(&range.end);
+            if start_offset > end_offset {
+                return Err(UpdateError::InvertedRange {
+                    start,
+                    start_offset,
+                    end,
+                    end_offset,
+                });
+            }
+            offsets.push((start_offset, end_offset, idx));
+        }
+
+        offsets.sort_unstable();
+        for pair in offsets.windows(2) {
+            let ((_, first_end, first), (second_start, _, second)) = (pair[0], pair[1]);
+            if first_end > second_start {
+                return Err(UpdateError::OverlappingEdits {
+                    first: edits[first].0,
+                    second: edits[second].0,
+                });
+            }
+        }
+
+        let removed: usize = offsets
+            .iter()
+            .map(|&(start_offset, end_offset, _)| (end_offset - start_offset) as usize)
+            .sum();
+        let inserted: usize = edits.iter().map(|(_, text)| text.len()).sum();
+        check_content_len(self.content.len() - removed + inserted)?;
+
+        // from the end, so that the offsets of the edits before stay valid
+        for &(start_offset, end_offset, idx) in offsets.iter().rev() {
+            self.replace_offsets(start_offset, end_offset, edits[idx].1);
+        }
+        Ok(())
+    }
+
+    /// Replace the content between `start_offset` and `end_offset` with `text`,
+    /// returning the range the inserted text occupies in the updated document
+    fn replace_offsets(&mut self, start_offset: u32, end_offset: u32, text: &str) -> Range {
+        self.content
+            .replace_range((start_offset as usize)..(end_offset as usize), text);
+
+        // Line starts before `start_offset` and after `end_offset` only depend on
+        // characters that weren't replaced, so they are kept, shifted by the change
+        // in length. The ones in between are recomputed, including both ends since
+        // a `\r` and a `\n` may have been joined or split at the boundaries.
+        let inserted_end_offset = start_offset + text.len() as u32;
+        let bytes = self.content.as_bytes();
+        let added_line_offsets: Vec<u32> = (start_offset..=inserted_end_offset)
+            .filter(|&offset| is_line_start(bytes, offset as usize))
+            .collect();
+        let num_added_line_offsets = added_line_offsets.len();
+
+        let splice_start = self
+            .line_offsets
+            .partition_point(|&line_offset| line_offset < start_offset);
+        let splice_end = self
+            .line_offsets
+            .partition_point(|&line_offset| line_offset <= end_offset);
+        self.line_offsets
+            .splice(splice_start..splice_end, added_line_offsets);
+        self.line_len_cache
+            .splice(splice_start..splice_end, num_added_line_offsets);
+        self.column_index.splice(
+            splice_start as u32..splice_end as u32,
+            num_added_line_offsets as u32,
+        );
+
+        // For edits near the top of a large document this loop touches most line
+        // offsets, see benches/update.rs. The tail is after `end_offset`, which is
+        // shifted to the end of the inserted text, so the shifted offsets fit in a
+        // `u32` and adding the difference modulo 2^32 is exact, while letting the
+        // loop vectorize.
+        let diff = (text.len() as u32).wrapping_sub(end_offset - start_offset);
+        if diff != 0 {
+            for line_offset in &mut self.line_offsets[splice_start + num_added_line_offsets..] {
+                *line_offset = line_offset.wrapping_add(diff);
+            }
+        }
+
+        self.debug_assert_invariants();
+        Range {
+            start: self.position_in_line(self.line_at(start_offset), start_offset),
+            end: self.position_in_line(self.line_at(inserted_end_offset), inserted_end_offset),
+        }
+    }
+
+    /// Asserts that the line offsets match the content, as if they were computed from
+    /// scratch. This is checked after every change in debug builds and compiled out
+    /// in release builds.
+    pub fn debug_assert_invariants(&self) {
+        debug_assert_eq!(
+            self.line_offsets,
+            computed_line_offsets(&self.content, true, None),
+            "The line offsets are inconsistent with the content {:?}",
+            self.content
+        );
+    }
+
+    /// The position of `offset`, which must be an offset on `line`. rounds down if
+    /// the offset is not on a character boundary.
+    fn position_in_line(&self, line: u32, offset: u32) -> Position {
+        let (text, line_offset) = self
+            .get_line_and_offset(line)
+            .expect("The line of the offset must be in the document");
+        Position {
+            line,
+            character: line_offset_code_units(text, offset - line_offset, self.position_encoding),
+        }
+    }
+
+    /// As demonstrated by test_multiple_position_same_offset(), in some cases,
+    /// there are multiple ways to reference the same Position. We map to a
+    /// "canonical Position" so we can avoid worrying about edge cases all over
+    /// the place.
+    ///
+    /// The canonical position is derived from the clamped offset, so positions
+    /// beyond the end of a line or of the document map to a valid position. Like
+    /// the LSP specification says, a character beyond the end of the line, e.g.
+    /// `u32::MAX`, is at the end of the line, before its terminator, as in
+    /// [`FullTextDocument::clamp_position`].
+    fn find_canonical_position(&self, position: &Position) -> (Position, u32) {
+        let offset = self.edit_offset(position);
+        let line = self.line_at(offset);
+        (self.position_in_line(line, offset), offset)
+    }
+
+    /// The offset of the canonical position of `position`, see
+    /// [`FullTextDocument::find_canonical_position`]
+    fn edit_offset(&self, position: &Position) -> u32 {
+        let offset = self.offset_at(*position);
+        match self.get_line_and_offset(position.line) {
+            Some((text, line_offset)) => {
+                offset.min(line_offset + strip_line_terminator(text).len() as u32)
+            }
+            None => offset,
+        }
+    }
+
+    /// The line that contains `offset`, an offset right after a line terminator is
+    /// on the next line
+    fn line_at(&self, offset: u32) -> u32 {
+        let next_line = self
+            .line_offsets
+            .partition_point(|&line_offset| line_offset <= offset);
+        // the first line offset is always 0, so `next_line` is at least 1
+        next_line as u32 - 1
+    }
+
+    /// Whether the content was created with a leading UTF-8 BOM, which was
+    /// stripped from it. Prepend `\u{FEFF}` to the content to write it back as it
+    /// was read.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "\u{FEFF}hello".to_string());
+    /// assert!(document.has_bom());
+    /// assert_eq!(document.get_content(None), "hello");
+    /// ```
+    pub fn has_bom(&self) -> bool {
+        self.has_bom
+    }
+
+    /// Document's language id
+    pub fn language_id(&self) -> &str {
+        &self.language_id
+    }
+
+    /// Override the document's language id, e.g. when the server detects that a
+    /// `plaintext` document is actually JSON
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    ///
+    /// let mut document = FullTextDocument::new("plaintext".to_string(), 1, "{}".to_string());
+    /// document.set_language_id("json".to_string());
+    /// assert_eq!(document.language_id(), "json");
+    /// ```
+    pub fn set_language_id(&mut self, language_id: String) {
+        self.language_id = language_id;
+    }
+
+    /// Document's version
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    /// The encoding positions of this document are counted in
+    pub fn position_encoding(&self) -> PositionEncoding {
+        self.position_encoding
+    }
+
+    /// The sync kind negotiated for this document, if it is enforced
+    pub fn sync_kind(&self) -> Option<TextDocumentSyncKind> {
+        self.sync_kind
+    }
+
+    /// Enforce the sync kind negotiated with the client, a document synced with
+    /// [`TextDocumentSyncKind::FULL`] rejects changes that carry a range
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::{FullTextDocument, UpdateError};
+    /// use lsp_types::{Position, Range, TextDocumentContentChangeEvent, TextDocumentSyncKind};
+    ///
+    /// let mut document = FullTextDocument::new("plain_text".to_string(), 1, "hello rust!".to_string());
+    /// document.set_sync_kind(TextDocumentSyncKind::FULL);
+    ///
+    /// let range = Range::new(Position::new(0, 0), Position::new(0, 5));
+    /// let change = TextDocumentContentChangeEvent {
+    ///     range: Some(range),
+    ///     range_length: None,
+    ///     text: "bye".to_string(),
+    /// };
+    /// let result = document.try_update(&[change], 2);
+    /// assert_eq!(result, Err(UpdateError::UnexpectedIncrementalChange { range }));
+    /// ```
+    pub fn set_sync_kind(&mut self, sync_kind: TextDocumentSyncKind) {
+        self.sync_kind = Some(sync_kind);
+    }
+
+    /// Get document content
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Range, Position};
+    ///
+    /// let text_documents = FullTextDocument::new("plain_text".to_string(), 1, "hello rust!".to_string());
+    ///
+    /// // get document all content
+    /// let content = text_documents.get_content(None);
+    /// assert_eq!(content, "hello rust!");
+    ///
+    /// // get document specify content by range
+    /// let (start, end) = (Position::new(0, 1), Position::new(0, 9));
+    /// let range = Range::new(start, end);
+    /// let sub_content = text_documents.get_content(Some(range));
+    /// assert_eq!(sub_content, "ello rus");
+    /// ```
+    ///
+    /// Positions inside a multi-byte character, e.g. because of a position encoding
+    /// mismatch, round down to the start of the character like
+    /// [`FullTextDocument::position_at`]. Both ends are clamped to the content, so a
+    /// range beyond the end of the document yields an empty string, as does an
+    /// inverted range, see [`FullTextDocument::try_get_content`] to detect it. It never
+    /// panics, whatever the range.
+    pub fn get_content(&self, range: Option<Range>) -> &str {
+        self.try_get_content(range).unwrap_or_default()
+    }
+
+    /// The whole document content, same as `get_content(None)`
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello rust!".to_string());
+    /// assert_eq!(document.text(), "hello rust!");
+    /// assert_eq!(document.text(), document.get_content(None));
+    /// ```
+    pub fn text(&self) -> &str {
+        &self.content
+    }
+
+    /// Get an owned copy of document content, see [`FullTextDocument::get_content`]
+    /// for the borrowing version
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Range, Position};
+    ///
+    /// let text_documents = FullTextDocument::new("plain_text".to_string(), 1, "hello rust!".to_string());
+    ///
+    /// let range = Range::new(Position::new(0, 1), Position::new(0, 9));
+    /// let sub_content: String = text_documents.get_content_owned(Some(range));
+    /// assert_eq!(sub_content, "ello rus");
+    /// ```
+    pub fn get_content_owned(&self, range: Option<Range>) -> String {
+        self.get_content(range).to_owned()
+    }
+
+    /// Get document content, returning `None` if the range can not be sliced from
+    /// the content, e.g. because its start is after its end
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Range, Position};
+    ///
+    /// let text_documents = FullTextDocument::new("plain_text".to_string(), 1, "hello rust!".to_string());
+    ///
+    /// let range = Range::new(Position::new(0, 1), Position::new(0, 9));
+    /// assert_eq!(text_documents.try_get_content(Some(range)), Some("ello rus"));
+    ///
+    /// let inverted = Range::new(Position::new(0, 9), Position::new(0, 1));
+    /// assert_eq!(text_documents.try_get_content(Some(inverted)), None);
+    /// ```
+    pub fn try_get_content(&self, range: Option<Range>) -> Option<&str> {
+        match range {
+            Some(Range { start, end }) => {
+                let start = self.offset_at(start).min(self.content_len());
+                let end = self.offset_at(end).min(self.content_len());
+                self.content.get(start as usize..end as usize)
+            }
+            None => Some(&self.content),
+        }
+    }
+
+    /// Get the content in a range of byte offsets, e.g. offsets kept from
+    /// [`FullTextDocument::offset_at`], without converting positions
+    ///
+    /// Offsets inside a character are rounded down to the start of the character.
+    /// Returns `None` if the range is inverted or ends beyond the content.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello \u{20AC}!".to_string());
+    /// assert_eq!(document.get_content_bytes(0..5), Some("hello"));
+    /// // 7 is inside the 3 bytes of `\u{20AC}`
+    /// assert_eq!(document.get_content_bytes(7..9), Some("\u{20AC}"));
+    /// assert_eq!(document.get_content_bytes(6..7), Some(""));
+    /// assert_eq!(document.get_content_bytes(6..99), None);
+    /// ```
+    pub fn get_content_bytes(&self, range: std::ops::Range<usize>) -> Option<&str> {
+        if range.start > range.end || range.end > self.content.len() {
+            return None;
+        }
+        let floor_char_boundary = |mut idx: usize| {
+            while !self.content.is_char_boundary(idx) {
+                idx -= 1;
+            }
+            idx
+        };
+        Some(&self.content[floor_char_boundary(range.start)..floor_char_boundary(range.end)])
+    }
+
+    fn get_line_and_offset(&self, line: u32) -> Option<(&str, u32)> {
+        self.line_offsets.get(line as usize).map(|&line_offset| {
+            let len: u32 = self.content_len();
+            let eol_offset = self.line_offsets.get((line + 1) as usize).unwrap_or(&len);
+            let line = &self.content[line_offset as usize..*eol_offset as usize];
+            (line, line_offset)
+        })
+    }
+
+    fn get_line(&self, line: u32) -> Option<&str> {
+        self.get_line_and_offset(line).map(|(line, _)| line)
+    }
+
+    /// Get the text of a line, excluding its terminator (`\n`, `\r\n` or `\r`)
+    ///
+    /// Returns `None` if the line is beyond the document.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\r\nrust!".to_string());
+    /// assert_eq!(document.line(0), Some("hello"));
+    /// assert_eq!(document.line(1), Some("rust!"));
+    /// assert_eq!(document.line(2), None);
+    /// ```
+    pub fn line(&self, line: u32) -> Option<&str> {
+        self.get_line(line).map(strip_line_terminator)
+    }
+
+    /// Get the text of a line, including its terminator (`\n`, `\r\n` or `\r`)
+    ///
+    /// Concatenating all lines gives the content. Returns `None` if the line is
+    /// beyond the document.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\r\nrust!".to_string());
+    /// assert_eq!(document.line_with_terminator(0), Some("hello\r\n"));
+    /// assert_eq!(document.line_with_terminator(1), Some("rust!"));
+    /// assert_eq!(document.line_with_terminator(2), None);
+    /// ```
+    pub fn line_with_terminator(&self, line: u32) -> Option<&str> {
+        self.get_line(line)
+    }
+
+    /// Get the text of a line, excluding its terminator, along with the byte offset
+    /// of its start in the content
+    ///
+    /// Adding the offset to a byte offset within the line gives the byte offset in
+    /// the content. Returns `None` if the line is beyond the document.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::Position;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\r\nrust!".to_string());
+    /// let (line, offset) = document.line_with_offset(1).unwrap();
+    /// assert_eq!((line, offset), ("rust!", 7));
+    ///
+    /// let token = line.find("st").unwrap() as u32;
+    /// assert_eq!(document.position_at(offset + token), Position::new(1, 2));
+    /// ```
+    pub fn line_with_offset(&self, line: u32) -> Option<(&str, u32)> {
+        self.get_line_and_offset(line)
+            .map(|(line, offset)| (strip_line_terminator(line), offset))
+    }
+
+    /// An iterator over the lines of the document, excluding their terminators
+    ///
+    /// Yields exactly [`FullTextDocument::line_count`] items, a trailing terminator
+    /// yields a final empty line.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\nrust!\n".to_string());
+    /// let lines: Vec<&str> = document.lines().collect();
+    /// assert_eq!(lines, vec!["hello", "rust!", ""]);
+    /// ```
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        let ends = self
+            .line_offsets
+            .iter()
+            .skip(1)
+            .copied()
+            .chain(std::iter::once(self.content_len()));
+        self.line_offsets
+            .iter()
+            .zip(ends)
+            .map(|(&start, end)| strip_line_terminator(&self.content[start as usize..end as usize]))
+    }
+
+    /// An iterator over the lines the range spans and the part of their text in the
+    /// range, excluding their terminators, e.g. for the visible lines of a
+    /// `semanticTokens/range` request
+    ///
+    /// The range is normalized first, see [`FullTextDocument::normalize_range`]. The
+    /// first and last lines are cut at the ends of the range, a range that ends at
+    /// the start of a line yields it as an empty text.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Position, Range};
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\r\nlsp\nrust!".to_string());
+    /// let range = Range::new(Position::new(0, 3), Position::new(2, 2));
+    /// let lines: Vec<(u32, &str)> = document.lines_in_range(range).collect();
+    /// assert_eq!(lines, vec![(0, "lo"), (1, "lsp"), (2, "ru")]);
+    /// ```
+    pub fn lines_in_range(&self, range: Range) -> impl Iterator<Item = (u32, &str)> + '_ {
+        let Range { start, end } = self.normalize_range(range);
+        let start_offset = self.offset_at(start) as usize;
+        let end_offset = self.offset_at(end) as usize;
+        (start.line..=end.line).map(move |line| {
+            let (text, line_offset) = self
+                .line_with_offset(line)
+                .expect("A normalized range is in the document");
+            let line_offset = line_offset as usize;
+            let from = start_offset.max(line_offset) - line_offset;
+            let to = end_offset.min(line_offset + text.len()) - line_offset;
+            (line, &text[from..to])
+        })
+    }
+
+    /// An iterator over the characters of the document and the position each one
+    /// starts at, including the characters of line terminators
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::Position;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "\u{10437}a\nb".to_string());
+    /// let chars: Vec<(Position, char)> = document.char_positions().collect();
+    /// assert_eq!(chars, vec![
+    ///     (Position::new(0, 0), '\u{10437}'),
+    ///     (Position::new(0, 2), 'a'),
+    ///     (Position::new(0, 3), '\n'),
+    ///     (Position::new(1, 0), 'b'),
+    /// ]);
+    /// ```
+    pub fn char_positions(&self) -> impl Iterator<Item = (Position, char)> + '_ {
+        let encoding = self.position_encoding;
+        (0..self.line_count()).flat_map(move |line| {
+            let text = self.line_with_terminator(line).unwrap_or_default();
+            let mut character = 0;
+            text.chars().map(move |char| {
+                let position = Position::new(line, character);
+                character += encoding.code_unit_len(char);
+                (position, char)
+            })
+        })
+    }
+
+    /// The length of a line in UTF-16 code units, excluding its terminator
+    ///
+    /// Returns `None` if the line is beyond the document.
+    pub fn line_len_utf16(&self, line: u32) -> Option<u32> {
+        self.line(line)
+            .map(|line| line.chars().map(|char| char.len_utf16() as u32).sum())
+    }
+
+    /// The length of a line in UTF-16 code units like
+    /// [`FullTextDocument::line_len_utf16`], cached if enabled with
+    /// [`FullTextDocument::set_line_len_cache`]
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    ///
+    /// let mut document = FullTextDocument::new("plain_text".to_string(), 1, "\u{10437}\nrust!".to_string());
+    /// document.set_line_len_cache(true);
+    /// assert_eq!(document.line_len_utf16_cached(0), Some(2));
+    /// assert_eq!(document.line_len_utf16_cached(1), Some(5));
+    /// assert_eq!(document.line_len_utf16_cached(2), None);
+    /// ```
+    pub fn line_len_utf16_cached(&self, line: u32) -> Option<u32> {
+        let text = self.line(line)?;
+        let len = || text.chars().map(|char| char.len_utf16() as u32).sum();
+        Some(self.line_len_cache.get_or_insert_with(line, len))
+    }
+
+    /// Cache the lengths [`FullTextDocument::line_len_utf16_cached`] computes, e.g.
+    /// for a server that asks for the same line lengths over and over
+    ///
+    /// The lengths are computed when first asked for, an update only forgets the
+    /// lengths of the lines it touches. The cache is disabled by default, it takes
+    /// 4 bytes per line.
+    pub fn set_line_len_cache(&mut self, enabled: bool) {
+        self.line_len_cache
+            .set_enabled(enabled, self.line_offsets.len());
+    }
+
+    /// The length of every line in UTF-16 code units, excluding their terminators
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "\u{10437}a\r\n\u{20AC}\n".to_string());
+    /// assert_eq!(document.line_lengths_utf16(), vec![3, 1, 0]);
+    /// ```
+    pub fn line_lengths_utf16(&self) -> Vec<u32> {
+        self.lines()
+            .map(|line| line.chars().map(|char| char.len_utf16() as u32).sum())
+            .collect()
+    }
+
+    /// The width of the leading spaces and tabs of a line in columns, where a tab
+    /// advances to the next multiple of `tab_size`, along with what they are
+    ///
+    /// Returns `None` if the line is beyond the document.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::{FullTextDocument, IndentKind};
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "fn main() {\n  \tlet a;\n}".to_string());
+    /// assert_eq!(document.line_indentation(0, 4), Some((0, IndentKind::None)));
+    /// assert_eq!(document.line_indentation(1, 4), Some((4, IndentKind::Mixed)));
+    /// assert_eq!(document.line_indentation(3, 4), None);
+    /// ```
+    pub fn line_indentation(&self, line: u32, tab_size: u32) -> Option<(u32, IndentKind)> {
+        let line = self.line(line)?;
+        let mut width = 0;
+        let (mut spaces, mut tabs) = (false, false);
+        for char in line.chars() {
+            match char {
+                ' ' => {
+                    width += 1;
+                    spaces = true;
+                }
+                '\t' => {
+                    width += tab_size - width % tab_size.max(1);
+                    tabs = true;
+                }
+                _ => break,
+            }
+        }
+        let kind = match (spaces, tabs) {
+            (false, false) => IndentKind::None,
+            (true, false) => IndentKind::Spaces,
+            (false, true) => IndentKind::Tabs,
+            (true, true) => IndentKind::Mixed,
+        };
+        Some((width, kind))
+    }
+
+    /// The range of the trailing spaces and tabs of a line, before its terminator
+    ///
+    /// Returns `None` if the line has no trailing whitespace or is beyond the
+    /// document.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Position, Range};
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello \t\r\nrust!".to_string());
+    /// assert_eq!(
+    ///     document.trailing_whitespace_range(0),
+    ///     Some(Range::new(Position::new(0, 5), Position::new(0, 7)))
+    /// );
+    /// assert_eq!(document.trailing_whitespace_range(1), None);
+    /// ```
+    pub fn trailing_whitespace_range(&self, line: u32) -> Option<Range> {
+        let text = self.line(line)?;
+        let trimmed = text.trim_end_matches([' ', '\t']);
+        if trimmed.len() == text.len() {
+            return None;
+        }
+        let character =
+            |offset: usize| line_offset_code_units(text, offset as u32, self.position_encoding);
+        Some(Range::new(
+            Position::new(line, character(trimmed.len())),
+            Position::new(line, character(text.len())),
+        ))
+    }
+
+    /// The ranges of the trailing spaces and tabs of every line, see
+    /// [`FullTextDocument::trailing_whitespace_range`]
+    pub fn trailing_whitespace_ranges(&self) -> Vec<Range> {
+        (0..self.line_count())
+            .filter_map(|line| self.trailing_whitespace_range(line))
+            .collect()
+    }
+
+    /// The range covering a whole line, excluding its terminator
+    ///
+    /// The end column is in code units of the document's position encoding.
+    /// Returns `None` if the line is beyond the document.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Position, Range};
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\r\nrust!".to_string());
+    /// assert_eq!(document.line_range(0), Some(Range::new(Position::new(0, 0), Position::new(0, 5))));
+    /// assert_eq!(document.line_range(1), Some(Range::new(Position::new(1, 0), Position::new(1, 5))));
+    /// assert_eq!(document.line_range(2), None);
+    /// ```
+    pub fn line_range(&self, line: u32) -> Option<Range> {
+        let (text, _) = self.get_line_and_offset(line)?;
+        let text = strip_line_terminator(text);
+        Some(Range {
+            start: Position { line, character: 0 },
+            end: Position {
+                line,
+                character: line_offset_code_units(text, text.len() as u32, self.position_encoding),
+            },
+        })
+    }
+
+    /// The byte offsets into the content at which each line starts
+    ///
+    /// The first element is always 0 and the offsets are strictly increasing.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "he\nllo\r\nrust!".to_string());
+    /// assert_eq!(document.line_offsets(), &[0, 3, 8]);
+    /// ```
+    pub fn line_offsets(&self) -> &[u32] {
+        &self.line_offsets
+    }
+
+    /// A amount of document content line
+    pub fn line_count(&self) -> u32 {
+        self.line_offsets
+            .len()
+            .try_into()
+            .expect("The number of lines of text passed in is too long")
+    }
+
+    /// The length of the document content in UTF-8 bytes
+    pub fn content_len(&self) -> u32 {
+        self.content
+            .len()
+            .try_into()
+            .expect("The length of the text passed in is too long")
+    }
+
+    /// The document content as UTF-8 bytes, e.g. for a byte oriented lexer
+    ///
+    /// The bytes are always valid UTF-8, byte offsets are the ones of
+    /// [`FullTextDocument::offset_at`] and [`FullTextDocument::position_at`].
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::Position;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\nrust!".to_string());
+    /// let offset = document.offset_at(Position::new(1, 0));
+    /// assert_eq!(&document.content_bytes()[offset as usize..], b"rust!");
+    /// assert_eq!(document.content_bytes().len() as u32, document.content_len());
+    /// ```
+    pub fn content_bytes(&self) -> &[u8] {
+        self.content.as_bytes()
+    }
+
+    /// Whether the content is empty
+    pub fn is_empty(&self) -> bool {
+        self.content.is_empty()
+    }
+
+    /// The position right after the last character of the document
+    ///
+    /// A trailing line terminator starts a final empty line, so the end position of
+    /// `"foo\n"` is `1:0` rather than `0:3`.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::Position;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\n\u{20AC} rust!".to_string());
+    /// assert_eq!(document.end_position(), Position::new(1, 7));
+    /// ```
+    pub fn end_position(&self) -> Position {
+        self.position_in_line(self.line_count() - 1, self.content_len())
+    }
+
+    /// The position right after the last character of the document that isn't
+    /// whitespace, e.g. where to insert a final newline
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::Position;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\nrust! \n\n".to_string());
+    /// assert_eq!(document.trimmed_end_position(), Position::new(1, 5));
+    /// ```
+    pub fn trimmed_end_position(&self) -> Position {
+        let offset = self.content.trim_end().len() as u32;
+        self.position_in_line(self.line_at(offset), offset)
+    }
+
+    /// The line terminator used by the document, `None` if it is a single line
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::{FullTextDocument, LineEnding};
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\r\nrust!\r\n".to_string());
+    /// assert_eq!(document.line_ending(), Some(LineEnding::CrLf));
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\nrust!\r\n".to_string());
+    /// assert_eq!(document.line_ending(), Some(LineEnding::Mixed));
+    /// ```
+    pub fn line_ending(&self) -> Option<LineEnding> {
+        match self.line_ending_counts() {
+            LineEndingCounts {
+                lf: 0,
+                crlf: 0,
+                cr: 0,
+            } => None,
+            LineEndingCounts { crlf: 0, cr: 0, .. } => Some(LineEnding::Lf),
+            LineEndingCounts { lf: 0, cr: 0, .. } => Some(LineEnding::CrLf),
+            LineEndingCounts { lf: 0, crlf: 0, .. } => Some(LineEnding::Cr),
+            _ => Some(LineEnding::Mixed),
+        }
+    }
+
+    /// Whether the document uses more than one kind of line terminator
+    pub fn has_mixed_line_endings(&self) -> bool {
+        self.line_ending() == Some(LineEnding::Mixed)
+    }
+
+    /// The amount of each kind of line terminator in the document
+    ///
+    /// Every line but the first one starts right after a terminator, so this only
+    /// looks at the line starts rather than scanning the whole content.
+    pub fn line_ending_counts(&self) -> LineEndingCounts {
+        let bytes = self.content.as_bytes();
+        let mut counts = LineEndingCounts::default();
+        for &line_offset in &self.line_offsets[1..] {
+            match bytes[..line_offset as usize] {
+                [.., b'\r', b'\n'] => counts.crlf += 1,
+                [.., b'\n'] => counts.lf += 1,
+                _ => counts.cr += 1,
+            }
+        }
+        counts
+    }
+
+    /// The length of the document content in UTF-16 code units
+    pub fn utf16_len(&self) -> u32 {
+        self.content
+            .chars()
+            .map(|char| char.len_utf16() as u32)
+            .sum()
+    }
+
+    /// The length of the document content in Unicode scalar values
+    pub fn char_count(&self) -> u32 {
+        self.content
+            .chars()
+            .count()
+            .try_into()
+            .expect("The length of the text passed in is too long")
+    }
+
+    /// Converts a zero-based byte offset in the UTF8-encoded content to a position
+    ///
+    /// the offset is in bytes, the position is in code units of the document's
+    /// position encoding. rounds down if the offset is not on a code unit boundary,
+    /// or is beyond the end of the content. An offset right after a line terminator
+    /// is at the start of the next line.
+    ///
+    /// `position_at` and [`FullTextDocument::offset_at`] round-trip: an offset on a
+    /// character boundary converts back to itself, and a position within a line
+    /// converts back to its canonical form, rounded down to a character boundary, or
+    /// the start of the next line if it is after the line terminator.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::Position;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "\u{10437}\r\nrust!".to_string());
+    /// assert_eq!(document.position_at(4), Position::new(0, 2));
+    /// assert_eq!(document.offset_at(Position::new(0, 2)), 4);
+    ///
+    /// // inside the surrogate pair of `\u{10437}`
+    /// assert_eq!(document.position_at(document.offset_at(Position::new(0, 1))), Position::new(0, 0));
+    /// // after the `\r\n`
+    /// assert_eq!(document.position_at(document.offset_at(Position::new(0, 4))), Position::new(1, 0));
+    /// ```
+    pub fn position_at(&self, offset: u32) -> Position {
+        let offset = offset.min(self.content_len());
+        self.position_in_line(self.line_at(offset), offset)
+    }
+
+    /// Converts a zero-based byte offset to a position like
+    /// [`FullTextDocument::position_at`], but reports offsets beyond the content
+    /// instead of clamping them
+    ///
+    /// The offset right after the last byte, i.e. the length of the content, is
+    /// valid.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::{FullTextDocument, PositionError};
+    /// use lsp_types::Position;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\nrust!".to_string());
+    /// assert_eq!(document.position_at_checked(11), Ok(Position::new(1, 5)));
+    /// assert_eq!(
+    ///     document.position_at_checked(12),
+    ///     Err(PositionError::OffsetOutOfBounds { offset: 12, content_len: 11 })
+    /// );
+    /// ```
+    pub fn position_at_checked(&self, offset: u32) -> Result<Position, PositionError> {
+        let content_len = self.content_len();
+        if offset > content_len {
+            return Err(PositionError::OffsetOutOfBounds {
+                offset,
+                content_len,
+            });
+        }
+        Ok(self.position_in_line(self.line_at(offset), offset))
+    }
+
+    /// Converts a position to a zero-based byte offset, suitable for slicing the
+    /// UTF-8 encoded content.
+    ///
+    /// the character is in code units of the document's position encoding. rounds
+    /// down if the character is not on a character boundary.
+    pub fn offset_at(&self, position: Position) -> u32 {
+        let Position { line, character } = position;
+        match self.get_line_and_offset(line) {
+            Some((line, offset)) => {
+                if self.position_encoding == PositionEncoding::Utf8 {
+                    // the character is already a byte offset within the line
+                    let mut idx = (character as usize).min(line.len());
+                    while !line.is_char_boundary(idx) {
+                        idx -= 1;
+                    }
+                    return offset + idx as u32;
+                }
+
+                if line.len() >= MIN_INDEXED_LINE_LEN {
+                    return offset
+                        + self.column_index.byte_offset(
+                            position.line,
+                            line,
+                            character,
+                            self.position_encoding,
+                        );
+                }
+
+                let mut c = 0;
+                let iter = line.char_indices();
+                for (idx, char) in iter {
+                    let len = self.position_encoding.code_unit_len(char);
+                    if c + len > character {
+                        return offset + idx as u32;
+                    }
+                    c += len;
+                }
+                offset + line.len() as u32
+            }
+            None => {
+                if line >= self.line_count() {
+                    self.content_len()
+                } else {
+                    0
+                }
+            }
+        }
+    }
+
+    /// Converts a position to a zero-based byte offset like
+    /// [`FullTextDocument::offset_at`], but reports positions beyond the document
+    /// instead of clamping them
+    ///
+    /// A character at the end of a line, before its terminator, is valid.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::{FullTextDocument, OffsetError};
+    /// use lsp_types::Position;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\nrust!".to_string());
+    /// assert_eq!(document.offset_at_checked(Position::new(1, 5)), Ok(11));
+    /// assert_eq!(
+    ///     document.offset_at_checked(Position::new(0, 6)),
+    ///     Err(OffsetError::CharacterOutOfBounds { line: 0, character: 6, line_len: 5 })
+    /// );
+    /// assert_eq!(
+    ///     document.offset_at_checked(Position::new(2, 0)),
+    ///     Err(OffsetError::LineOutOfBounds { line: 2, line_count: 2 })
+    /// );
+    /// ```
+    pub fn offset_at_checked(&self, position: Position) -> Result<u32, OffsetError> {
+        let Position { line, character } = position;
+        let line_range = self.line_range(line).ok_or(OffsetError::LineOutOfBounds {
+            line,
+            line_count: self.line_count(),
+        })?;
+        if character > line_range.end.character {
+            return Err(OffsetError::CharacterOutOfBounds {
+                line,
+                character,
+                line_len: line_range.end.character,
+            });
+        }
+        Ok(self.offset_at(position))
+    }
+
+    /// Whether the position addresses the content, i.e. its line exists and its
+    /// character is at most the length of the line, excluding its terminator
+    ///
+    /// See [`FullTextDocument::offset_at_checked`] for why a position doesn't.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::Position;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\nrust!".to_string());
+    /// assert!(document.contains_position(Position::new(0, 5)));
+    /// assert!(!document.contains_position(Position::new(0, 6)));
+    /// assert!(!document.contains_position(Position::new(2, 0)));
+    /// ```
+    pub fn contains_position(&self, position: Position) -> bool {
+        self.offset_at_checked(position).is_ok()
+    }
+
+    /// Swap the ends of the range if it is inverted and clamp them to the document,
+    /// the start of the returned range is never after its end
+    ///
+    /// A line beyond the document is clamped to the end of the document, a character
+    /// beyond the end of its line to the end of the line, excluding its terminator.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Position, Range};
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\nrust!".to_string());
+    /// let range = document.normalize_range(Range::new(Position::new(3, 0), Position::new(0, 9)));
+    /// assert_eq!(range, Range::new(Position::new(0, 5), Position::new(1, 5)));
+    /// ```
+    pub fn normalize_range(&self, range: Range) -> Range {
+        let start = self.clamp_position(range.start);
+        let end = self.clamp_position(range.end);
+        if (start.line, start.character) > (end.line, end.character) {
+            Range::new(end, start)
+        } else {
+            Range::new(start, end)
+        }
+    }
+
+    /// Clamp the ends of the range to the document like
+    /// [`FullTextDocument::normalize_range`], without reordering them
+    pub fn clamp_range(&self, range: Range) -> Range {
+        Range::new(
+            self.clamp_position(range.start),
+            self.clamp_position(range.end),
+        )
+    }
+
+    /// Whether the range spans more than one line, once normalized like in
+    /// [`FullTextDocument::normalize_range`]
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Position, Range};
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\nrust!".to_string());
+    /// assert!(document.is_multiline_range(Range::new(Position::new(0, 2), Position::new(1, 0))));
+    /// // the end is clamped to the end of the document
+    /// assert!(!document.is_multiline_range(Range::new(Position::new(1, 2), Position::new(9, 0))));
+    /// assert_eq!(document.range_line_count(Range::new(Position::new(1, 0), Position::new(0, 2))), 2);
+    /// ```
+    pub fn is_multiline_range(&self, range: Range) -> bool {
+        self.range_line_count(range) > 1
+    }
+
+    /// The amount of lines the range spans, once normalized like in
+    /// [`FullTextDocument::normalize_range`], which is at least 1
+    ///
+    /// A range that ends at the start of a line spans that line too.
+    pub fn range_line_count(&self, range: Range) -> u32 {
+        let range = self.normalize_range(range);
+        range.end.line - range.start.line + 1
+    }
+
+    /// The range covered by both `a` and `b`, or `None` if they don't meet
+    ///
+    /// The ranges are normalized first, see [`FullTextDocument::normalize_range`].
+    /// Ranges that only touch intersect in an empty range.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Position, Range};
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\nrust!".to_string());
+    /// let a = Range::new(Position::new(0, 2), Position::new(1, 3));
+    /// let b = Range::new(Position::new(1, 1), Position::new(1, 9));
+    /// assert_eq!(document.intersect(a, b), Some(Range::new(Position::new(1, 1), Position::new(1, 3))));
+    ///
+    /// let c = Range::new(Position::new(0, 0), Position::new(0, 1));
+    /// assert_eq!(document.intersect(b, c), None);
+    /// ```
+    pub fn intersect(&self, a: Range, b: Range) -> Option<Range> {
+        let (a, b) = (self.normalize_range(a), self.normalize_range(b));
+        let key = |position: Position| (position.line, position.character);
+        let start = std::cmp::max_by_key(a.start, b.start, |&position| key(position));
+        let end = std::cmp::min_by_key(a.end, b.end, |&position| key(position));
+        (key(start) <= key(end)).then(|| Range::new(start, end))
+    }
+
+    /// The closest position that addresses the content, see
+    /// [`FullTextDocument::normalize_range`]
+    fn clamp_position(&self, position: Position) -> Position {
+        match self.line_range(position.line) {
+            Some(line_range) => {
+                let character = position.character.min(line_range.end.character);
+                let offset = self.offset_at(Position::new(position.line, character));
+                self.position_in_line(position.line, offset)
+            }
+            None => self.end_position(),
+        }
+    }
+
+    /// Converts many positions to offsets at once, see [`FullTextDocument::offset_at`]
+    ///
+    /// The positions are processed in document order, so each line is walked at
+    /// most once. The offsets are returned in the order of `positions`.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::Position;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\nrust!".to_string());
+    /// let offsets = document.offsets_at(&[Position::new(1, 2), Position::new(0, 1)]);
+    /// assert_eq!(offsets, vec![8, 1]);
+    /// ```
+    pub fn offsets_at(&self, positions: &[Position]) -> Vec<u32> {
+        let mut order: Vec<usize> = (0..positions.len()).collect();
+        order.sort_by_key(|&i| (positions[i].line, positions[i].character));
+
+        let mut offsets = vec![0; positions.len()];
+        let mut order = order.into_iter().peekable();
+        while let Some(&first) = order.peek() {
+            let line = positions[first].line;
+            let on_line = |&i: &usize| positions[i].line == line;
+            match self.get_line_and_offset(line) {
+                Some((text, _))
+                    if self.position_encoding == PositionEncoding::Utf8
+                        || text.len() >= MIN_INDEXED_LINE_LEN =>
+                {
+                    // no need to walk the line, see `offset_at`
+                    while let Some(i) = order.next_if(on_line) {
+                        offsets[i] = self.offset_at(positions[i]);
+                    }
+                }
+                Some((text, line_offset)) => {
+                    let mut chars = text.char_indices().peekable();
+                    let mut c = 0;
+                    while let Some(i) = order.next_if(on_line) {
+                        let character = positions[i].character;
+                        while let Some(&(_, char)) = chars.peek() {
+                            let len = self.position_encoding.code_unit_len(char);
+                            if c + len > character {
+                                break;
+                            }
+                            c += len;
+                            chars.next();
+                        }
+                        let idx = chars.peek().map_or(text.len(), |&(idx, _)| idx);
+                        offsets[i] = line_offset + idx as u32;
+                    }
+                }
+                None => {
+                    // the line is beyond the document
+                    while let Some(i) = order.next_if(on_line) {
+                        offsets[i] = self.content_len();
+                    }
+                }
+            }
+        }
+        offsets
+    }
+
+    /// Converts many offsets to positions at once, see [`FullTextDocument::position_at`]
+    ///
+    /// The offsets are processed in ascending order, so `line_offsets` is walked
+    /// once. The positions are returned in the order of `offsets`.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::Position;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello\nrust!".to_string());
+    /// let positions = document.positions_at(&[8, 1]);
+    /// assert_eq!(positions, vec![Position::new(1, 2), Position::new(0, 1)]);
+    /// ```
+    pub fn positions_at(&self, offsets: &[u32]) -> Vec<Position> {
+        let mut order: Vec<usize> = (0..offsets.len()).collect();
+        order.sort_by_key(|&i| offsets[i]);
+
+        let mut positions = vec![Position::default(); offsets.len()];
+        let mut line = 0;
+        for i in order {
+            let offset = offsets[i].min(self.content_len());
+            while line + 1 < self.line_offsets.len() && self.line_offsets[line + 1] <= offset {
+                line += 1;
+            }
+            positions[i] = self.position_in_line(line as u32, offset);
+        }
+        positions
+    }
+
+    /// Find every non-overlapping occurrence of `needle`, an empty needle has no
+    /// occurrences
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Position, Range};
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "foo bar\nfoo".to_string());
+    /// let ranges = document.find_all("foo");
+    /// assert_eq!(ranges, vec![
+    ///     Range::new(Position::new(0, 0), Position::new(0, 3)),
+    ///     Range::new(Position::new(1, 0), Position::new(1, 3)),
+    /// ]);
+    /// ```
+    pub fn find_all(&self, needle: &str) -> Vec<Range> {
+        if needle.is_empty() {
+            return vec![];
+        }
+        let matches = self
+            .content
+            .match_indices(needle)
+            .map(|(idx, _)| idx..idx + needle.len());
+        self.byte_ranges_to_ranges(matches)
+    }
+
+    /// Like [`FullTextDocument::find_all`], but ASCII letters match regardless of
+    /// their case
+    pub fn find_all_ignore_ascii_case(&self, needle: &str) -> Vec<Range> {
+        if needle.is_empty() {
+            return vec![];
+        }
+        // lowercasing ASCII letters keeps the byte offsets unchanged
+        let content = self.content.to_ascii_lowercase();
+        let needle = needle.to_ascii_lowercase();
+        let matches = content
+            .match_indices(&needle)
+            .map(|(idx, _)| idx..idx + needle.len());
+        self.byte_ranges_to_ranges(matches)
+    }
+
+    /// Find the first occurrence of `needle` that starts after `after`, e.g. to go to
+    /// the next match from the cursor. With `wrap`, the search continues from the
+    /// start of the document. An empty needle has no occurrences.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Position, Range};
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "foo bar\nfoo".to_string());
+    /// let next = document.find_from("foo", Position::new(0, 0), false);
+    /// assert_eq!(next, Some(Range::new(Position::new(1, 0), Position::new(1, 3))));
+    ///
+    /// assert_eq!(document.find_from("foo", Position::new(1, 0), false), None);
+    /// let wrapped = document.find_from("foo", Position::new(1, 0), true);
+    /// assert_eq!(wrapped, Some(Range::new(Position::new(0, 0), Position::new(0, 3))));
+    /// ```
+    pub fn find_from(&self, needle: &str, after: Position, wrap: bool) -> Option<Range> {
+        if needle.is_empty() {
+            return None;
+        }
+        let offset = self.offset_at(after) as usize;
+        // the occurrences start at the next character, which `offset` is the start of
+        let from = self.content[offset..]
+            .chars()
+            .next()
+            .map_or(self.content.len(), |char| offset + char.len_utf8());
+        let start = match self.content[from..].find(needle) {
+            Some(idx) => from + idx,
+            None if wrap => self.content.find(needle)?,
+            None => return None,
+        };
+        Some(self.byte_range_to_range(start..start + needle.len()))
+    }
+
+    /// Find every match of `regex`, matches including line terminators span
+    /// multiple lines
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Position, Range};
+    /// use regex::Regex;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "// TODO: a\n// FIXME: b".to_string());
+    /// let regex = Regex::new(r"TODO|FIXME").unwrap();
+    /// let ranges = document.find_regex(&regex);
+    /// assert_eq!(ranges, vec![
+    ///     Range::new(Position::new(0, 3), Position::new(0, 7)),
+    ///     Range::new(Position::new(1, 3), Position::new(1, 8)),
+    /// ]);
+    /// ```
+    #[cfg(feature = "regex")]
+    pub fn find_regex(&self, regex: &regex::Regex) -> Vec<Range> {
+        self.byte_ranges_to_ranges(regex.find_iter(&self.content).map(|m| m.range()))
+    }
+
+    /// Converts byte ranges of the content to ranges at once
+    fn byte_ranges_to_ranges(
+        &self,
+        byte_ranges: impl Iterator<Item = std::ops::Range<usize>>,
+    ) -> Vec<Range> {
+        let offsets: Vec<u32> = byte_ranges
+            .flat_map(|byte_range| [byte_range.start as u32, byte_range.end as u32])
+            .collect();
+        self.positions_at(&offsets)
+            .chunks_exact(2)
+            .map(|positions| Range::new(positions[0], positions[1]))
+            .collect()
+    }
+
+    /// Converts a byte range of the content to a range, see
+    /// [`FullTextDocument::positions_at`]
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Position, Range};
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "\u{10437}a\nrust!".to_string());
+    /// let range = document.byte_range_to_range(4..8);
+    /// assert_eq!(range, Range::new(Position::new(0, 2), Position::new(1, 2)));
+    /// ```
+    pub fn byte_range_to_range(&self, bytes: std::ops::Range<usize>) -> Range {
+        self.byte_ranges_to_ranges(std::iter::once(bytes))[0]
+    }
+
+    /// Converts a range to a byte range of the content, suitable for slicing the
+    /// UTF-8 encoded content, see [`FullTextDocument::offset_at`]
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Position, Range};
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "\u{10437}a\nrust!".to_string());
+    /// let bytes = document.range_to_byte_range(Range::new(Position::new(0, 2), Position::new(1, 2)));
+    /// assert_eq!(bytes, 4..8);
+    /// ```
+    pub fn range_to_byte_range(&self, range: Range) -> std::ops::Range<usize> {
+        self.offset_at(range.start) as usize..self.offset_at(range.end) as usize
+    }
+
+    /// The edits that turn the content into `new_text`, with ranges in code units
+    /// of the document's position encoding
+    ///
+    /// Like the edits of a [`lsp_types::WorkspaceEdit`], the ranges refer to the
+    /// current content, are in document order and don't overlap. Lines are diffed
+    /// first, then each edit is narrowed to the characters that differ. Identical
+    /// texts yield no edits.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Position, Range, TextEdit};
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "fn main() {\nlet a=1;\n}".to_string());
+    /// let edits = document.diff("fn main() {\n    let a = 1;\n}");
+    /// assert_eq!(edits, vec![
+    ///     TextEdit::new(Range::new(Position::new(1, 0), Position::new(1, 6)), "    let a = ".to_string()),
+    /// ]);
+    /// ```
+    pub fn diff(&self, new_text: &str) -> Vec<TextEdit> {
+        let hunks = diff::diff(&self.content, new_text);
+        let ranges =
+            self.byte_ranges_to_ranges(hunks.iter().map(|(old_bytes, _)| old_bytes.clone()));
+        ranges
+            .into_iter()
+            .zip(hunks)
+            .map(|(range, (_, new_bytes))| TextEdit::new(range, new_text[new_bytes].to_string()))
+            .collect()
+    }
+
+    /// The incremental changes that turn the content into `new_text` when passed to
+    /// [`FullTextDocument::update`], e.g. to replay a new version of a file as a
+    /// client would
+    ///
+    /// The changes are the edits of [`FullTextDocument::diff`], from the last one to
+    /// the first one, so that the range of each change is still valid once the
+    /// changes after it are applied.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    ///
+    /// let mut document = FullTextDocument::new("plain_text".to_string(), 1, "a\nb\nc".to_string());
+    /// let changes = document.changes_to("x\nb\ny");
+    /// assert_eq!(changes.len(), 2);
+    ///
+    /// document.update(&changes, 2);
+    /// assert_eq!(document.get_content(None), "x\nb\ny");
+    /// ```
+    pub fn changes_to(&self, new_text: &str) -> Vec<TextDocumentContentChangeEvent> {
+        self.diff(new_text)
+            .into_iter()
+            .rev()
+            .map(|edit| TextDocumentContentChangeEvent {
+                range: Some(edit.range),
+                range_length: None,
+                text: edit.new_text,
+            })
+            .collect()
+    }
+
+    /// Get the character at the given position
+    ///
+    /// A position inside a character (e.g. between the surrogates of a UTF-16
+    /// pair) reads the character it is in. Returns `None` at the end of a line
+    /// and at the end of the document.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::Position;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "a\u{10437}\nb".to_string());
+    /// assert_eq!(document.char_at(Position::new(0, 1)), Some('\u{10437}'));
+    /// assert_eq!(document.char_at(Position::new(0, 3)), None);
+    /// assert_eq!(document.char_at(Position::new(1, 0)), Some('b'));
+    /// ```
+    pub fn char_at(&self, position: Position) -> Option<char> {
+        let (line, offset) = self.line_and_offset_in_line(position)?;
+        line[offset..].chars().next()
+    }
+
+    /// Get the character right before the given position, e.g. a trigger character
+    /// the cursor is after
+    ///
+    /// Unlike [`FullTextDocument::char_at`], line terminators count: the character
+    /// before the start of a line is the end of the previous line's terminator.
+    /// Returns `None` at the start of the document. The position is clamped like in
+    /// [`FullTextDocument::offset_at`].
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::Position;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "foo.\u{10437}\nb".to_string());
+    /// assert_eq!(document.char_before(Position::new(0, 4)), Some('.'));
+    /// assert_eq!(document.char_before(Position::new(0, 6)), Some('\u{10437}'));
+    /// assert_eq!(document.char_before(Position::new(1, 0)), Some('\n'));
+    /// assert_eq!(document.char_before(Position::new(0, 0)), None);
+    /// ```
+    pub fn char_before(&self, position: Position) -> Option<char> {
+        let offset = self.offset_at(position) as usize;
+        self.content[..offset].chars().next_back()
+    }
+
+    /// Get the character right after the given position, including line terminators
+    ///
+    /// Returns `None` at the end of the document. The position is clamped like in
+    /// [`FullTextDocument::offset_at`].
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::Position;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "foo(\r\nb".to_string());
+    /// assert_eq!(document.char_after(Position::new(0, 3)), Some('('));
+    /// assert_eq!(document.char_after(Position::new(0, 4)), Some('\r'));
+    /// assert_eq!(document.char_after(Position::new(1, 1)), None);
+    /// ```
+    pub fn char_after(&self, position: Position) -> Option<char> {
+        let offset = self.offset_at(position) as usize;
+        self.content[offset..].chars().next()
+    }
+
+    /// Get up to `max_len` code units of the text right before the given position on
+    /// its line, e.g. the partial identifier or path a completion is requested for
+    ///
+    /// The text never extends to the previous line, and a character that doesn't fit
+    /// in `max_len` entirely is left out. A position beyond the end of its line is at
+    /// the end of the line, a position beyond the document has no text before it.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::Position;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "foo\nuse std::co".to_string());
+    /// assert_eq!(document.text_before(Position::new(1, 11), 7), "std::co");
+    /// assert_eq!(document.text_before(Position::new(1, 11), 100), "use std::co");
+    /// assert_eq!(document.text_before(Position::new(1, 0), 100), "");
+    /// ```
+    pub fn text_before(&self, position: Position, max_len: u32) -> &str {
+        let Some((line, offset)) = self.line_and_offset_in_line(position) else {
+            return "";
+        };
+        let before = &line[..offset];
+        let mut start = offset;
+        let mut len = 0;
+        for (idx, char) in before.char_indices().rev() {
+            len += self.position_encoding.code_unit_len(char);
+            if len > max_len {
+                break;
+            }
+            start = idx;
+        }
+        &before[start..]
+    }
+
+    /// Get the grapheme cluster at the given position
+    ///
+    /// A position inside a grapheme cluster reads the whole cluster, so a flag
+    /// emoji reads as one unit. Returns `None` at the end of a line and at the
+    /// end of the document.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::Position;
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "a\u{1F1EB}\u{1F1F7}b".to_string());
+    /// assert_eq!(document.grapheme_at(Position::new(0, 3)), Some("\u{1F1EB}\u{1F1F7}"));
+    /// assert_eq!(document.grapheme_at(Position::new(0, 5)), Some("b"));
+    /// assert_eq!(document.grapheme_at(Position::new(0, 6)), None);
+    /// ```
+    #[cfg(feature = "unicode-segmentation")]
+    pub fn grapheme_at(&self, position: Position) -> Option<&str> {
+        use unicode_segmentation::UnicodeSegmentation;
+
+        let (line, offset) = self.line_and_offset_in_line(position)?;
+        line.grapheme_indices(true)
+            .find(|&(idx, grapheme)| offset < idx + grapheme.len())
+            .map(|(_, grapheme)| grapheme)
+    }
+
+    /// The line of `position` without its terminator, along with the byte
+    /// offset of `position` within it
+    fn line_and_offset_in_line(&self, position: Position) -> Option<(&str, usize)> {
+        let (line, line_offset) = self.get_line_and_offset(position.line)?;
+        let line = strip_line_terminator(line);
+        let offset = ((self.offset_at(position) - line_offset) as usize).min(line.len());
+        Some((line, offset))
+    }
+
+    /// Get the word at the given position along with its range
+    ///
+    /// A word is a run of alphanumeric characters and `_`, see
+    /// [`FullTextDocument::word_at_with`] to customize it. Returns `None` if the
+    /// character at the position isn't a word character, such as whitespace or the
+    /// end of a line, a word never spans multiple lines.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::FullTextDocument;
+    /// use lsp_types::{Position, Range};
+    ///
+    /// let document = FullTextDocument::new("plain_text".to_string(), 1, "hello rust_lang!".to_string());
+    /// let (range, word) = document.word_at(Position::new(0, 8)).unwrap();
+    /// assert_eq!(word, "rust_lang");
+    /// assert_eq!(range, Range::new(Position::new(0, 6), Position::new(0, 15)));
+    /// ```
+    pub fn word_at(&self, position: Position) -> Option<(Range, &str)> {
+        self.word_at_with(position, |char| char.is_alphanumeric() || char == '_')
+    }
+
+    /// Get the word at the given position along with its range, where a word is
+    /// a run of characters matching `is_word_char`
+    ///
+    /// See [`FullTextDocument::word_at`].
+    pub fn word_at_with(
+        &self,
+        position: Position,
+        is_word_char: impl Fn(char) -> bool,
+    ) -> Option<(Range, &str)> {
+        let (line, offset) = self.line_and_offset_in_line(position)?;
+        if !matches!(line[offset..].chars().next(), Some(char) if is_word_char(char)) {
+            return None;
+        }
+
+        let start = line[..offset]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, char)| is_word_char(char))
+            .last()
+            .map_or(offset, |(idx, _)| idx);
+        let end = line[offset..]
+            .char_indices()
+            .find(|&(_, char)| !is_word_char(char))
+            .map_or(line.len(), |(idx, _)| offset + idx);
+
+        let range = Range {
+            start: Position {
+                line: position.line,
+                character: line_offset_code_units(line, start as u32, self.position_encoding),
+            },
+            end: Position {
+                line: position.line,
+                character: line_offset_code_units(line, end as u32, self.position_encoding),
+            },
+        };
+        Some((range, &line[start..end]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Random;
+
+    fn full_text_document() -> FullTextDocument {
+        FullTextDocument::new(
+            "js".to_string(),
+            2,
+            "he\nllo\nworld\r\nfoo\rbar".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_offset_at() {
+        let text_document = full_text_document();
+
+        let offset = text_document.offset_at(Position {
+            line: 1,
+            character: 1,
+        });
+        assert_eq!(offset, 4);
+
+        let offset = text_document.offset_at(Position {
+            line: 2,
+            character: 3,
+        });
+        assert_eq!(offset, 10);
+
+        // the `f` in `foo` (\r\n is a single line terminator)
+        let offset = text_document.offset_at(Position {
+            line: 3,
+            character: 1,
+        });
+        assert_eq!(offset, 15);
+    }
+
+    /// basic multilingual plane
+    #[test]
+    fn test_offset_at_bmp() {
+        // Euro symbol
+        let text_document = FullTextDocument::new("js".to_string(), 2, "\u{20AC} euro".to_string());
+
+        let offset = text_document.offset_at(Position {
+            line: 0,
+            // E euro
+            //   ^
+            character: 2,
+        });
+        assert_eq!(offset, 4);
+    }
+
+    /// supplementary multilingual plane, aka surrogate pair
+    #[test]
+    fn test_offset_at_smp() {
+        // Deseret Small Letter Yee
+        let text_document = FullTextDocument::new("js".to_string(), 2, "\u{10437} yee".to_string());
+        let offset = text_document.offset_at(Position {
+            line: 0,
+            // HL yee
+            //    ^
+            character: 3,
+        });
+        assert_eq!(offset, 5);
+    }
+
+    /// a character beyond the end of the line should clamp to the end of the line
+    #[test]
+    fn test_offset_at_beyond_end_of_line() {
+        let text_document =
+            FullTextDocument::new("js".to_string(), 2, "\u{20AC} abc\nline 2".to_string());
+        // "\u{20AC} abc\nline 2" in UTF-8:
+        // \xE2 \x82 \xAC \x20 \x61 \x62 \x63 \x0A \x6C \x69 \x6E \x65 \x20 \x32
+        // ^ line 1 == 0                           ^ line 2 == 8
+        assert_eq!(text_document.line_offsets, vec![0, 8]);
+
+        let offset = text_document.offset_at(Position {
+            line: 0,
+            character: 100,
+        });
+        assert_eq!(offset, 8);
+    }
+
+    #[test]
+    fn test_offset_at_checked() {
+        let text_document = full_text_document();
+        assert_eq!(text_document.offset_at_checked(Position::new(0, 2)), Ok(2));
+        assert_eq!(text_document.offset_at_checked(Position::new(2, 5)), Ok(12));
+        assert_eq!(text_document.offset_at_checked(Position::new(4, 3)), Ok(21));
+        assert_eq!(
+            text_document.offset_at_checked(Position::new(2, 6)),
+            Err(OffsetError::CharacterOutOfBounds {
+                line: 2,
+                character: 6,
+                line_len: 5
+            })
+        );
+        assert_eq!(
+            text_document.offset_at_checked(Position::new(5, 0)),
+            Err(OffsetError::LineOutOfBounds {
+                line: 5,
+                line_count: 5
+            })
+        );
+
+        let text_document = FullTextDocument::new("js".to_string(), 2, "\u{10437}".to_string());
+        assert_eq!(text_document.offset_at_checked(Position::new(0, 2)), Ok(4));
+        assert!(text_document
+            .offset_at_checked(Position::new(0, 3))
+            .is_err());
+    }
+
+    #[test]
+    fn test_empty() {
+        let text_document = FullTextDocument::empty("js".to_string());
+        assert_eq!(text_document.language_id(), "js");
+        assert_eq!(text_document.version(), 0);
+        assert_eq!(text_document.line_offsets(), &[0]);
+        assert_eq!(text_document.line_count(), 1);
+        assert_eq!(text_document.line(0), Some(""));
+        assert_eq!(text_document.end_position(), Position::new(0, 0));
+
+        let text_document = FullTextDocument::default();
+        assert_eq!(text_document.language_id(), "plaintext");
+        assert_eq!(text_document.line_offsets(), &[0]);
+        assert_eq!(text_document, FullTextDocument::builder().build());
+    }
+
+    #[test]
+    fn test_builder() {
+        let text_document = FullTextDocument::builder().build();
+        assert_eq!(text_document.language_id(), "plaintext");
+        assert_eq!(text_document.version(), 0);
+        assert_eq!(text_document.get_content(None), "");
+        assert_eq!(text_document.position_encoding(), PositionEncoding::Utf16);
+        assert_eq!(text_document.line_offsets(), &[0]);
+
+        let text_document = FullTextDocument::builder()
+            .language_id("js")
+            .version(2)
+            .content("he\nllo\nworld\r\nfoo\rbar")
+            .build();
+        assert_eq!(text_document, full_text_document());
+        assert_eq!(text_document.line_offsets(), &[0, 3, 7, 14, 18]);
+    }
+
+    #[test]
+    fn test_strip_bom() {
+        let text_document =
+            FullTextDocument::new("js".to_string(), 2, "\u{FEFF}hello\nrust".to_string());
+        assert!(text_document.has_bom());
+        assert_eq!(text_document.offset_at(Position::new(0, 0)), 0);
+        assert_eq!(text_document.char_at(Position::new(0, 0)), Some('h'));
+        assert_eq!(text_document.line_offsets(), &[0, 6]);
+
+        let text_document = FullTextDocument::new("js".to_string(), 2, "hello\u{FEFF}".to_string());
+        assert!(!text_document.has_bom());
+        assert_eq!(text_document.get_content(None), "hello\u{FEFF}");
+    }
+
+    #[test]
+    fn test_contains_position() {
+        let text_document = full_text_document();
+        assert!(text_document.contains_position(Position::new(0, 0)));
+        assert!(text_document.contains_position(Position::new(2, 5)));
+        assert!(!text_document.contains_position(Position::new(2, 6)));
+        assert!(text_document.contains_position(Position::new(4, 3)));
+        assert!(!text_document.contains_position(Position::new(5, 0)));
+
+        let text_document = FullTextDocument::with_encoding(
+            "js".to_string(),
+            2,
+            "\u{10437}".to_string(),
+            PositionEncoding::Utf32,
+        );
+        assert!(text_document.contains_position(Position::new(0, 1)));
+        assert!(!text_document.contains_position(Position::new(0, 2)));
+    }
+
+    #[test]
+    fn test_range_line_count() {
+        let text_document = full_text_document();
+        let cases = [
+            ((0, 0), (0, 2), 1),
+            ((0, 1), (1, 0), 2),
+            ((2, 2), (1, 1), 2),
+            ((3, 1), (9, 9), 2),
+            ((9, 0), (9, 5), 1),
+            ((0, 9), (4, 0), 5),
+        ];
+        for (start, end, line_count) in cases {
+            let range = Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1));
+            assert_eq!(
+                text_document.range_line_count(range),
+                line_count,
+                "{range:?}"
+            );
+            assert_eq!(
+                text_document.is_multiline_range(range),
+                line_count > 1,
+                "{range:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_normalize_range() {
+        let text_document = full_text_document();
+        let cases = [
+            (
+                Range::new(Position::new(1, 1), Position::new(2, 2)),
+                Range::new(Position::new(1, 1), Position::new(2, 2)),
+            ),
+            (
+                Range::new(Position::new(2, 2), Position::new(1, 1)),
+                Range::new(Position::new(1, 1), Position::new(2, 2)),
+            ),
+            (
+                Range::new(Position::new(0, 9), Position::new(2, 9)),
+                Range::new(Position::new(0, 2), Position::new(2, 5)),
+            ),
+            (
+                Range::new(Position::new(9, 0), Position::new(3, 1)),
+                Range::new(Position::new(3, 1), Position::new(4, 3)),
+            ),
+            (
+                Range::new(Position::new(1, 3), Position::new(1, 0)),
+                Range::new(Position::new(1, 0), Position::new(1, 3)),
+            ),
+        ];
+        for (range, normalized) in cases {
+            assert_eq!(text_document.normalize_range(range), normalized);
+        }
+
+        // between the surrogates of U+10437
+        let text_document = FullTextDocument::new("js".to_string(), 2, "\u{10437}".to_string());
+        assert_eq!(
+            text_document.normalize_range(Range::new(Position::new(0, 1), Position::new(0, 3))),
+            Range::new(Position::new(0, 0), Position::new(0, 2))
+        );
+    }
+
+    #[test]
+    fn test_clamp_range() {
+        let text_document = full_text_document();
+        assert_eq!(
+            text_document.clamp_range(Range::new(Position::new(9, 9), Position::new(0, 9))),
+            Range::new(Position::new(4, 3), Position::new(0, 2))
+        );
+    }
+
+    #[test]
+    fn test_intersect() {
+        let text_document = full_text_document();
+        let range = |start: (u32, u32), end: (u32, u32)| {
+            Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+        };
+        let cases = [
+            (
+                range((0, 1), (2, 3)),
+                range((1, 0), (4, 1)),
+                Some(range((1, 0), (2, 3))),
+            ),
+            (
+                range((1, 0), (4, 1)),
+                range((0, 1), (2, 3)),
+                Some(range((1, 0), (2, 3))),
+            ),
+            (
+                range((0, 0), (4, 3)),
+                range((2, 1), (2, 2)),
+                Some(range((2, 1), (2, 2))),
+            ),
+            (
+                range((0, 0), (1, 1)),
+                range((1, 1), (2, 0)),
+                Some(range((1, 1), (1, 1))),
+            ),
+            (range((0, 0), (1, 0)), range((1, 1), (2, 0)), None),
+            // inverted and beyond the document
+            (range((2, 3), (0, 1)), range((9, 0), (3, 9)), None),
+            (
+                range((3, 1), (0, 1)),
+                range((9, 0), (3, 0)),
+                Some(range((3, 0), (3, 1))),
+            ),
+            // the end of line 0 is before the start of line 1
+            (range((0, 0), (0, 9)), range((1, 0), (1, 1)), None),
+        ];
+        for (a, b, intersection) in cases {
+            assert_eq!(text_document.intersect(a, b), intersection, "{a:?} {b:?}");
+        }
+    }
+
+    #[test]
+    fn test_document_trait_object() {
+        let mut text_document: Box<dyn Document> = Box::new(FullTextDocument::new(
+            "js".to_string(),
+            1,
+            "hello\nrust!".to_string(),
+        ));
+        text_document.update(
+            &[TextDocumentContentChangeEvent {
+                range: Some(Range::new(Position::new(1, 0), Position::new(1, 4))),
+                range_length: None,
+                text: "lsp".to_string(),
+            }],
+            2,
+        );
+        assert_eq!(text_document.version(), 2);
+        assert_eq!(text_document.get_content(None), "hello\nlsp!");
+        assert_eq!(text_document.line_count(), 2);
+        assert_eq!(text_document.offset_at(Position::new(1, 1)), 7);
+        assert_eq!(text_document.position_at(7), Position::new(1, 1));
+    }
+
+    #[test]
+    fn test_offset_at_long_lines() {
+        let line = "a\u{20AC}b\u{10437}c".repeat(100);
+        let mut text_document =
+            FullTextDocument::new("js".to_string(), 2, format!("{line}\r\n{line}"));
+        let walked = |text_document: &FullTextDocument, position: Position| {
+            let (line, offset) = text_document.get_line_and_offset(position.line).unwrap();
+            let mut c = 0;
+            for (idx, char) in line.char_indices() {
+                c += char.len_utf16() as u32;
+                if c > position.character {
+                    return offset + idx as u32;
+                }
+            }
+            offset + line.len() as u32
+        };
+        for line in 0..2 {
+            for character in 0..700 {
+                let position = Position::new(line, character);
+                assert_eq!(
+                    text_document.offset_at(position),
+                    walked(&text_document, position),
+                    "{position:?}"
+                );
+            }
+        }
+
+        // the index is rebuilt after a change
+        text_document.update(
+            &[TextDocumentContentChangeEvent {
+                range: Some(Range::new(Position::new(1, 0), Position::new(1, 1))),
+                range_length: None,
+                text: "\u{10437}".to_string(),
+            }],
+            3,
+        );
+        assert_eq!(text_document.offset_at(Position::new(1, 2)), 1006);
+        assert_eq!(text_document.offset_at(Position::new(1, 3)), 1009);
+    }
+
+    #[test]
+    fn test_offset_at_utf8() {
+        let text_document = FullTextDocument::with_encoding(
+            "js".to_string(),
+            2,
+            "\u{20AC} euro\n\u{10437} yee".to_string(),
+            PositionEncoding::Utf8,
+        );
+
+        // E euro
+        //   ^
+        let offset = text_document.offset_at(Position {
+            line: 0,
+            character: 4,
+        });
+        assert_eq!(offset, 4);
+
+        // not on a character boundary, round down
+        let offset = text_document.offset_at(Position {
+            line: 1,
+            character: 2,
+        });
+        assert_eq!(offset, 9);
+
+        // beyond the end of the line
+        let offset = text_document.offset_at(Position {
+            line: 0,
+            character: 100,
+        });
+        assert_eq!(offset, 9);
+    }
+
+    #[test]
+    fn test_content_lengths() {
+        let text_document =
+            FullTextDocument::new("js".to_string(), 2, "\u{20AC} \u{10437}\r\n".to_string());
+        assert_eq!(text_document.content_len(), 10);
+        assert_eq!(text_document.utf16_len(), 6);
+        assert_eq!(text_document.char_count(), 5);
+    }
+
+    #[test]
+    fn test_end_position() {
+        let text_document = full_text_document();
+        assert_eq!(text_document.end_position(), Position::new(4, 3));
+
+        let text_document = FullTextDocument::new("js".to_string(), 2, "foo\r\n".to_string());
+        assert_eq!(text_document.end_position(), Position::new(1, 0));
+
+        let text_document = FullTextDocument::new("js".to_string(), 2, "".to_string());
+        assert_eq!(text_document.end_position(), Position::new(0, 0));
+
+        let text_document = FullTextDocument::with_encoding(
+            "js".to_string(),
+            2,
+            "\u{10437}".to_string(),
+            PositionEncoding::Utf8,
+        );
+        assert_eq!(text_document.end_position(), Position::new(0, 4));
+    }
+
+    #[test]
+    fn test_trimmed_end_position() {
+        let cases = [
+            ("", Position::new(0, 0)),
+            (" \r\n\t\n", Position::new(0, 0)),
+            ("he\nllo", Position::new(1, 3)),
+            ("he\nllo\r\n", Position::new(1, 3)),
+            ("he\n\u{10437}\u{3000}\r\r\n \n", Position::new(1, 2)),
+        ];
+        for (content, position) in cases {
+            let text_document = FullTextDocument::new("js".to_string(), 2, content.to_string());
+            assert_eq!(
+                text_document.trimmed_end_position(),
+                position,
+                "{content:?}"
+            );
+            assert_eq!(text_document.is_empty(), content.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_line_ending() {
+        let text_document = full_text_document();
+        assert_eq!(text_document.line_ending(), Some(LineEnding::Mixed));
+        assert!(text_document.has_mixed_line_endings());
+        assert_eq!(
+            text_document.line_ending_counts(),
+            LineEndingCounts {
+                lf: 2,
+                crlf: 1,
+                cr: 1,
+            }
+        );
+
+        let text_document = FullTextDocument::new("js".to_string(), 2, "a\rb\r".to_string());
+        assert_eq!(text_document.line_ending(), Some(LineEnding::Cr));
+        assert!(!text_document.has_mixed_line_endings());
+
+        let text_document = FullTextDocument::new("js".to_string(), 2, "a\nb\n\n".to_string());
+        assert_eq!(text_document.line_ending(), Some(LineEnding::Lf));
+
+        let text_document = FullTextDocument::new("js".to_string(), 2, "a".to_string());
+        assert_eq!(text_document.line_ending(), None);
+    }
+
+    #[test]
+    fn test_position_at() {
+        let text_document = full_text_document();
+
+        let position = text_document.position_at(5);
+        assert_eq!(
+            position,
+            Position {
+                line: 1,
+                character: 2
+            }
+        );
+
+        let position = text_document.position_at(11);
+        assert_eq!(
+            position,
+            Position {
+                line: 2,
+                character: 4,
+            }
+        );
+
+        let position = text_document.position_at(15);
+        assert_eq!(
+            position,
+            Position {
+                line: 3,
+                character: 1,
+            }
+        );
+
+        let position = text_document.position_at(0);
+        assert_eq!(
+            position,
+            Position {
+                line: 0,
+                character: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_position_at_checked() {
+        let text_document = full_text_document();
+        assert_eq!(
+            text_document.position_at_checked(0),
+            Ok(Position::new(0, 0))
+        );
+        assert_eq!(
+            text_document.position_at_checked(5),
+            Ok(Position::new(1, 2))
+        );
+        assert_eq!(
+            text_document.position_at_checked(14),
+            Ok(Position::new(3, 0))
+        );
+        assert_eq!(
+            text_document.position_at_checked(21),
+            Ok(Position::new(4, 3))
+        );
+        assert_eq!(
+            text_document.position_at_checked(22),
+            Err(PositionError::OffsetOutOfBounds {
+                offset: 22,
+                content_len: 21
+            })
+        );
+    }
+
+    /// basic multilingual plane
+    #[test]
+    fn test_position_at_bmp() {
+        // Euro symbol
+        let text_document = FullTextDocument::new("js".to_string(), 2, "\u{20AC} euro".to_string());
+        let position = text_document.position_at(4);
+        assert_eq!(
+            position,
+            Position {
+                line: 0,
+                // E euro
+                //   ^
+                character: 2,
+            }
+        );
+
+        // multi-line content
+        let text_document =
+            FullTextDocument::new("js".to_string(), 2, "\n\n\u{20AC} euro\n\n".to_string());
+        let position = text_document.position_at(6);
+        assert_eq!(
+            position,
+            Position {
+                line: 2,
+                // E euro
+                //   ^
+                character: 2,
+            }
+        );
+    }
+
+    /// supplementary multilingual plane, aka surrogate pair
+    #[test]
+    fn test_position_at_smp() {
+        // Deseret Small Letter Yee
+        let text_document = FullTextDocument::new("js".to_string(), 2, "\u{10437} yee".to_string());
+        assert_eq!(
+            text_document.position_at(5),
+            Position {
+                line: 0,
+                // HL yee
+                //    ^
+                character: 3,
+            }
+        );
+
+        // \u{10437} is 4 bytes wide. if not on a char boundary, round down
+        assert_eq!(
+            text_document.position_at(2),
+            Position {
+                line: 0,
+                character: 0,
+            }
+        );
+
+        // multi-line content
+        let text_document =
+            FullTextDocument::new("js".to_string(), 2, "\n\n\u{10437} yee\n\n".to_string());
+        let position = text_document.position_at(7);
+        assert_eq!(
+            position,
+            Position {
+                line: 2,
+                // HL yee
+                //    ^
+                character: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn test_offsets_at() {
+        let text_document = FullTextDocument::new(
+            "js".to_string(),
+            2,
+            "he\nl\u{10437}o\nworld\r\nfoo\rbar".to_string(),
+        );
+        let positions = [
+            Position::new(3, 1),
+            Position::new(1, 3),
+            Position::new(100, 0),
+            Position::new(1, 2),
+            Position::new(0, 100),
+            Position::new(1, 0),
+        ];
+        let expected: Vec<u32> = positions
+            .iter()
+            .map(|&position| text_document.offset_at(position))
+            .collect();
+        assert_eq!(text_document.offsets_at(&positions), expected);
+    }
+
+    #[test]
+    fn test_positions_at() {
+        let text_document = FullTextDocument::new(
+            "js".to_string(),
+            2,
+            "he\nl\u{10437}o\nworld\r\nfoo\rbar".to_string(),
+        );
+        let positions = text_document.positions_at(&[19, 100, 3, 6, 0, 8]);
+        assert_eq!(
+            positions,
+            vec![
+                Position::new(3, 2),
+                Position::new(4, 3),
+                Position::new(1, 0),
+                // not on a character boundary, round down
+                Position::new(1, 1),
+                Position::new(0, 0),
+                Position::new(1, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_position_at_utf8() {
+        let text_document = FullTextDocument::with_encoding(
+            "js".to_string(),
+            2,
+            "\u{20AC} euro\n\u{10437} yee".to_string(),
+            PositionEncoding::Utf8,
+        );
+
+        assert_eq!(
+            text_document.position_at(4),
+            Position {
+                line: 0,
+                character: 4,
+            }
+        );
+        // HL yee
+        //    ^
+        assert_eq!(
+            text_document.position_at(14),
+            Position {
+                line: 1,
+                character: 5,
+            }
+        );
+        // not on a character boundary, round down
+        assert_eq!(
+            text_document.position_at(11),
+            Position {
+                line: 1,
+                character: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_line() {
+        let text_document = full_text_document();
+        assert_eq!(text_document.line(0), Some("he"));
+        assert_eq!(text_document.line(2), Some("world"));
+        assert_eq!(text_document.line(3), Some("foo"));
+        // the last line doesn't end with a terminator
+        assert_eq!(text_document.line(4), Some("bar"));
+        assert_eq!(text_document.line(5), None);
+
+        // a trailing newline yields a final empty line
+        let text_document = FullTextDocument::new("js".to_string(), 2, "foo\r\n".to_string());
+        assert_eq!(text_document.line(0), Some("foo"));
+        assert_eq!(text_document.line(1), Some(""));
+    }
+
+    #[test]
+    fn test_line_with_offset() {
+        let text_document = full_text_document();
+        let lines: Vec<_> = (0..6)
+            .map(|line| text_document.line_with_offset(line))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                Some(("he", 0)),
+                Some(("llo", 3)),
+                Some(("world", 7)),
+                Some(("foo", 14)),
+                Some(("bar", 18)),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_try_update_never_panics() {
+        let change = |start: Position, end: Position, text: &str| TextDocumentContentChangeEvent {
+            range: Some(Range::new(start, end)),
+            range_length: None,
+            text: text.to_string(),
+        };
+        let max = u32::MAX;
+        let changes = [
+            change(Position::new(max, max), Position::new(max, max), "\r"),
+            change(Position::new(0, max), Position::new(max, 0), "\n\r\n"),
+            change(Position::new(1, 1), Position::new(0, 1), "x"),
+            change(Position::new(max, 0), Position::new(0, 0), ""),
+            change(Position::new(2, 5), Position::new(2, max), "y"),
+            change(Position::new(2, 1), Position::new(2, 2), "\r\u{10437}\n"),
+        ];
+        for encoding in [
+            PositionEncoding::Utf8,
+            PositionEncoding::Utf16,
+            PositionEncoding::Utf32,
+        ] {
+            let line = "\u{20AC}".repeat(100);
+            let content = format!("\u{10437}a\r\nb\r{line}\n{line}");
+            let mut text_document =
+                FullTextDocument::with_encoding("js".to_string(), 0, content, encoding);
+            for (version, change) in changes.iter().enumerate() {
+                let _ = text_document.try_update(std::slice::from_ref(change), version as i32 + 1);
+                text_document.debug_assert_invariants();
+            }
+            let _ = text_document.try_update(&changes, i32::MAX);
+            text_document.debug_assert_invariants();
+        }
+    }
+
+    #[test]
+    fn test_update_owned() {
+        let change = |range: Option<Range>, text: &str| TextDocumentContentChangeEvent {
+            range,
+            range_length: None,
+            text: text.to_string(),
+        };
+        let mut text_document = full_text_document();
+        let full_text = change(None, "hello\nrust!");
+        let insert = change(
+            Some(Range::new(Position::new(1, 0), Position::new(1, 0))),
+            "lsp ",
+        );
+        text_document.update_owned(vec![change(None, "overridden"), full_text, insert], 3);
+
+        assert_eq!(text_document.get_content(None), "hello\nlsp rust!");
+        assert_eq!(text_document.version(), 3);
+        text_document.debug_assert_invariants();
+
+        // the full text is moved into the content
+        let full_text = change(None, "moved");
+        let full_text_ptr = full_text.text.as_ptr();
+        text_document.update_owned(vec![full_text], 4);
+        assert_eq!(text_document.get_content(None), "moved");
+        assert_eq!(text_document.content.as_ptr(), full_text_ptr);
+    }
+
+    #[test]
+    fn test_update_reporting() {
+        let change =
+            |start: (u32, u32), end: (u32, u32), text: &str| TextDocumentContentChangeEvent {
+                range: Some(Range::new(
+                    Position::new(start.0, start.1),
+                    Position::new(end.0, end.1),
+                )),
+                range_length: None,
+                text: text.to_string(),
+            };
+        let content = "0\n1\n2\n3\n4\n5\n6\n7\n8\n9";
+        let mut text_document = FullTextDocument::new("js".to_string(), 1, content.to_string());
+
+        assert!(text_document.update_reporting(&[], 2).is_empty());
+        assert_eq!(text_document.version(), 2);
+
+        // an edit within a line, then lines added above it shift it
+        let changes = [
+            change((5, 0), (5, 1), "x"),
+            change((1, 1), (1, 1), "\na\nb"),
+        ];
+        assert_eq!(text_document.update_reporting(&changes, 3), 1..=7);
+        assert_eq!(text_document.line(7), Some("x"));
+
+        // lines removed, then a later edit within the joined line
+        let changes = [change((2, 0), (5, 0), ""), change((2, 0), (2, 0), "y")];
+        assert_eq!(text_document.update_reporting(&changes, 4), 2..=2);
+        assert_eq!(text_document.line(2), Some("y3"));
+
+        // an edit below lines removed after it
+        let changes = [change((8, 0), (8, 1), "z"), change((0, 0), (2, 0), "")];
+        assert_eq!(text_document.update_reporting(&changes, 5), 0..=6);
+        assert_eq!(text_document.line(6), Some("z"));
+
+        let full = TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: "a\nb".to_string(),
+        };
+        assert_eq!(text_document.update_reporting(&[full], 6), 0..=1);
+
+        // an end beyond the line removes none of its terminator
+        let mut text_document = FullTextDocument::new("js".to_string(), 1, content.to_string());
+        let changes = [
+            change((5, 0), (5, 1), "w"),
+            change((1, 0), (1, u32::MAX), "v"),
+        ];
+        assert_eq!(text_document.update_reporting(&changes, 2), 1..=5);
+        assert_eq!(text_document.line(5), Some("w"));
+    }
+
+    #[test]
+    fn test_computed_line_offsets() {
+        for content in [
+            "",
+            "a\nb\r\nc\rd\n",
+            "\r\r\n\n\r",
+            "\u{20AC}\n\u{10437}\r\n",
+        ] {
+            let line_offsets = computed_line_offsets(content, true, None);
+            let expected: Vec<u32> = (0..=content.len())
+                .filter(|&idx| is_line_start(content.as_bytes(), idx))
+                .map(|idx| idx as u32)
+                .collect();
+            assert_eq!(line_offsets, expected, "{content:?}");
+        }
+
+        // preallocated exactly for `\n` and `\r\n` terminators
+        let line_offsets = computed_line_offsets(&"a\r\nb\n".repeat(1000), true, None);
+        assert_eq!(line_offsets.len(), 2001);
+        assert_eq!(line_offsets.capacity(), 2001);
+    }
+
+    #[test]
+    fn test_position_at_every_offset() {
+        let content = "ab\n\r\n\u{20AC}\r\u{10437}c\r\n\n\rd\r";
+        for encoding in [
+            PositionEncoding::Utf8,
+            PositionEncoding::Utf16,
+            PositionEncoding::Utf32,
+        ] {
+            let text_document = FullTextDocument::with_encoding(
+                "text".to_string(),
+                0,
+                content.to_string(),
+                encoding,
+            );
+            for offset in 0..=content.len() as u32 {
+                let position = text_document.position_at(offset);
+                let mut canonical = offset as usize;
+                while !content.is_char_boundary(canonical) {
+                    canonical -= 1;
+                }
+                assert_eq!(
+                    text_document.offset_at(position),
+                    canonical as u32,
+                    "{offset} at {position:?} in {encoding:?}"
+                );
+                assert_eq!(
+                    position.line,
+                    text_document.line_at(offset),
+                    "{offset} in {encoding:?}"
+                );
+            }
+            // a line start is never the end of the previous line
+            for (line, &line_offset) in text_document.line_offsets.iter().enumerate() {
+                assert_eq!(
+                    text_document.position_at(line_offset),
+                    Position::new(line as u32, 0)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_position_offset_round_trip() {
+        // long enough for the column index
+        let long_lines = "a\u{10437}\u{20AC}".repeat(40) + "\r\n" + &"\u{20AC}b".repeat(90);
+        let contents = [
+            "hello\nrust!",
+            "\u{20AC}uro \u{00E9}t\u{00E9}\n\u{4E2D}\u{6587}",
+            "\u{10437}\u{10437}\n\u{1F600}a\u{1F600}",
+            "a\u{10437}\r\n\u{20AC}\r\rb\n\u{1F600}\r\n",
+            &long_lines,
+        ];
+        for content in contents {
+            for encoding in [
+                PositionEncoding::Utf8,
+                PositionEncoding::Utf16,
+                PositionEncoding::Utf32,
+            ] {
+                let text_document = FullTextDocument::with_encoding(
+                    "text".to_string(),
+                    0,
+                    content.to_string(),
+                    encoding,
+                );
+
+                for offset in (0..=content.len()).filter(|&idx| content.is_char_boundary(idx)) {
+                    let position = text_document.position_at(offset as u32);
+                    assert_eq!(
+                        text_document.offset_at(position),
+                        offset as u32,
+                        "{content:?} {encoding:?}"
+                    );
+                }
+
+                for line in 0..text_document.line_count() {
+                    let line_text = text_document.line_with_terminator(line).unwrap();
+                    let units: u32 = line_text.chars().map(|c| encoding.code_unit_len(c)).sum();
+                    // the start of each character, and the end of the line
+                    let mut starts = vec![0];
+                    for c in line_text.chars() {
+                        starts.push(starts.last().unwrap() + encoding.code_unit_len(c));
+                    }
+                    for character in 0..=units {
+                        let position = Position::new(line, character);
+                        let canonical = if character == units
+                            && line_text != text_document.line(line).unwrap()
+                        {
+                            // after the terminator
+                            Position::new(line + 1, 0)
+                        } else {
+                            let start = starts.iter().rev().find(|&&start| start <= character);
+                            Position::new(line, *start.unwrap())
+                        };
+
+                        let offset = text_document.offset_at(position);
+                        assert_eq!(
+                            text_document.position_at(offset),
+                            canonical,
+                            "{position:?} of {content:?} {encoding:?}"
+                        );
+                        // an edit beyond the end of the line is at its end, before the
+                        // terminator
+                        let stripped_units: u32 = text_document
+                            .line(line)
+                            .unwrap()
+                            .chars()
+                            .map(|c| encoding.code_unit_len(c))
+                            .sum();
+                        let edit_position = if character > stripped_units {
+                            Position::new(line, stripped_units)
+                        } else {
+                            canonical
+                        };
+                        assert_eq!(
+                            text_document.find_canonical_position(&position),
+                            (edit_position, text_document.offset_at(edit_position))
+                        );
+                        // a canonical position is its own canonical form
+                        assert_eq!(text_document.offset_at(canonical), offset);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_column_index_after_update() {
+        // long enough for the column index
+        let line = "a\u{10437}\u{20AC}".repeat(40);
+        let content = format!("{line}\n{line}\r\n{line}\n{line}");
+        let changes = [
+            ((1, 3), (1, 3), "x"),
+            ((0, 0), (0, 0), "\n"),
+            ((2, 10), (3, 7), ""),
+            ((3, 0), (3, 0), "\u{20AC}\n\u{10437}"),
+            ((0, 0), (1, 0), ""),
+        ];
+        for encoding in [PositionEncoding::Utf16, PositionEncoding::Utf32] {
+            let mut document =
+                FullTextDocument::with_encoding("text".to_string(), 0, content.clone(), encoding);
+            for ((start_line, start_char), (end_line, end_char), text) in changes {
+                // index every line before the change
+                for line in 0..document.line_count() {
+                    document.offset_at(Position::new(line, 50));
+                }
+                let range = Range::new(
+                    Position::new(start_line, start_char),
+                    Position::new(end_line, end_char),
+                );
+                document.update(
+                    &[TextDocumentContentChangeEvent {
+                        range: Some(range),
+                        range_length: None,
+                        text: text.to_string(),
+                    }],
+                    document.version() + 1,
+                );
+
+                let fresh = FullTextDocument::with_encoding(
+                    "text".to_string(),
+                    0,
+                    document.text().to_string(),
+                    encoding,
+                );
+                for line in 0..document.line_count() {
+                    for character in (0..200).step_by(7) {
+                        let position = Position::new(line, character);
+                        assert_eq!(
+                            document.offset_at(position),
+                            fresh.offset_at(position),
+                            "{position:?} {encoding:?}"
+                        );
+                    }
+                    let positions: Vec<_> = (0..200).map(|c| Position::new(line, c)).collect();
+                    assert_eq!(
+                        document.offsets_at(&positions),
+                        fresh.offsets_at(&positions)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_line_offsets_builder() {
+        let mut random = Random(11);
+        let mut contents = vec!["".to_string(), "\r".to_string(), "a\r\r\nb\r".to_string()];
+        contents.extend((0..200).map(|_| random.text(12)));
+        for content in &contents {
+            let expected = computed_line_offsets(content, true, None);
+            // every split in two chunks, e.g. between a `\r` and a `\n`
+            for split in 0..=content.len() {
+                let mut builder = LineOffsetsBuilder::new();
+                builder.push(&content.as_bytes()[..split]);
+                builder.push(&content.as_bytes()[split..]);
+                assert_eq!(builder.finish(), expected, "{content:?} split at {split}");
+            }
+        }
+
+        // a chunk of the reader ends between a `\r` and a `\n`
+        let content = format!(
+            "{}\r\n\r{}\r",
+            "a".repeat(64 * 1024 - 1),
+            "b".repeat(70_000)
+        );
+        let text_document =
+            FullTextDocument::from_reader("text".to_string(), 1, content.as_bytes()).unwrap();
+        assert_eq!(
+            text_document,
+            FullTextDocument::new("text".to_string(), 1, content.clone())
+        );
+        assert_eq!(
+            text_document.line_offsets,
+            computed_line_offsets(&content, true, None)
+        );
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let file: &[u8] = b"\xEF\xBB\xBFhello\r\nrust!";
+        let text_document = FullTextDocument::from_reader("text".to_string(), 1, file).unwrap();
+        assert!(text_document.has_bom());
+        assert_eq!(text_document.get_content(None), "hello\r\nrust!");
+        assert_eq!(text_document.line_offsets, vec![0, 7]);
+
+        let file: &[u8] = b"hello\xFF";
+        let err = FullTextDocument::from_reader("text".to_string(), 1, file).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_check_content_len() {
+        let max_len = FullTextDocument::max_supported_len();
+        assert_eq!(check_content_len(0), Ok(()));
+        assert_eq!(check_content_len(max_len), Ok(()));
+        assert_eq!(
+            check_content_len(max_len + 1),
+            Err(UpdateError::ContentTooLong { len: max_len + 1 })
+        );
+        // the line count of the longest content fits as well
+        assert!(u32::try_from(max_len + 1).is_ok());
+    }
+
+    #[test]
+    fn test_splice() {
+        let mut text_document = FullTextDocument::new("js".to_string(), 1, String::new());
+        let end = text_document
+            .splice(Range::default(), "fn main() {\r\n}")
+            .unwrap();
+        assert_eq!(end, Position::new(1, 1));
+
+        let insert = Range::new(Position::new(0, 11), Position::new(0, 11));
+        let end = text_document.splice(insert, "\r\n    \u{10437};").unwrap();
+        assert_eq!(end, Position::new(1, 7));
+        assert_eq!(
+            text_document.get_content(None),
+            "fn main() {\r\n    \u{10437};\r\n}"
+        );
+        assert_eq!(text_document.line_count(), 3);
+        assert_eq!(text_document.version(), 1);
+
+        // removing the `\r` of a `\r\n` keeps the line count
+        let remove = Range::new(Position::new(1, 7), Position::new(1, 8));
+        assert_eq!(text_document.splice(remove, ""), Ok(Position::new(1, 7)));
+        assert_eq!(text_document.line_count(), 3);
+
+        let inverted = Range::new(Position::new(1, 1), Position::new(0, 1));
+        assert!(matches!(
+            text_document.splice(inverted, "x"),
+            Err(UpdateError::InvertedRange { .. })
+        ));
+    }
+
+    #[test]
+    fn test_line_with_terminator() {
+        let text_document = full_text_document();
+        let lines: Vec<_> = (0..6)
+            .map(|line| text_document.line_with_terminator(line))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                Some("he\n"),
+                Some("llo\n"),
+                Some("world\r\n"),
+                Some("foo\r"),
+                Some("bar"),
+                None
+            ]
+        );
+
+        for content in ["", "\n", "a\r\n", "\r\r\n\n", "a\nb\r"] {
+            let text_document = FullTextDocument::new("js".to_string(), 1, content.to_string());
+            let lines: String = (0..text_document.line_count())
+                .filter_map(|line| text_document.line_with_terminator(line))
+                .collect();
+            assert_eq!(lines, content);
+        }
+    }
+
+    #[test]
+    fn test_lines_in_range() {
+        let text_document = FullTextDocument::new(
+            "js".to_string(),
+            2,
+            "a\u{10437}b\r\n\u{20AC}\rcd\n".to_string(),
+        );
+        let cases = [
+            ((0, 1), (0, 3), vec![(0, "\u{10437}")]),
+            // inside the surrogate pair
+            ((0, 2), (1, 1), vec![(0, "\u{10437}b"), (1, "\u{20AC}")]),
+            ((0, 9), (2, 0), vec![(0, ""), (1, "\u{20AC}"), (2, "")]),
+            ((2, 1), (0, 3), vec![(0, "b"), (1, "\u{20AC}"), (2, "c")]),
+            ((2, 1), (9, 9), vec![(2, "d"), (3, "")]),
+            ((9, 0), (9, 9), vec![(3, "")]),
+        ];
+        for (start, end, expected) in cases {
+            let range = Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1));
+            let lines: Vec<(u32, &str)> = text_document.lines_in_range(range).collect();
+            assert_eq!(lines, expected, "{range:?}");
+        }
+    }
+
+    #[test]
+    fn test_char_positions() {
+        let content = "a\u{10437}\r\n\u{20AC}\rb\n\n\u{1F600}";
+        for encoding in [
+            PositionEncoding::Utf8,
+            PositionEncoding::Utf16,
+            PositionEncoding::Utf32,
+        ] {
+            let text_document = FullTextDocument::with_encoding(
+                "text".to_string(),
+                0,
+                content.to_string(),
+                encoding,
+            );
+            let expected: Vec<(Position, char)> = content
+                .char_indices()
+                .map(|(idx, char)| (text_document.position_at(idx as u32), char))
+                .collect();
+            let char_positions: Vec<(Position, char)> = text_document.char_positions().collect();
+            assert_eq!(char_positions, expected, "{encoding:?}");
+        }
+
+        let text_document = FullTextDocument::new("text".to_string(), 0, String::new());
+        assert_eq!(text_document.char_positions().next(), None);
+    }
+
+    #[test]
+    fn test_lines() {
+        let text_document = full_text_document();
+        let lines: Vec<&str> = text_document.lines().collect();
+        assert_eq!(lines, vec!["he", "llo", "world", "foo", "bar"]);
+        assert_eq!(lines.len() as u32, text_document.line_count());
+
+        let text_document = FullTextDocument::new("js".to_string(), 2, "foo\n\r\n".to_string());
+        let lines: Vec<&str> = text_document.lines().collect();
+        assert_eq!(lines, vec!["foo", "", ""]);
+        assert_eq!(lines.len() as u32, text_document.line_count());
+    }
+
+    #[test]
+    fn test_line_lengths_utf16() {
+        let text_document = full_text_document();
+        assert_eq!(text_document.line_lengths_utf16(), vec![2, 3, 5, 3, 3]);
+
+        let text_document = FullTextDocument::new(
+            "js".to_string(),
+            2,
+            "a\u{20AC}\u{10437}\r\u{10437}\u{10437}\r\n".to_string(),
+        );
+        let line_lengths = text_document.line_lengths_utf16();
+        assert_eq!(line_lengths, vec![4, 4, 0]);
+        for (line, len) in line_lengths.into_iter().enumerate() {
+            assert_eq!(text_document.line_len_utf16(line as u32), Some(len));
+        }
+    }
+
+    #[test]
+    fn test_line_indentation() {
+        let text_document = FullTextDocument::new(
+            "js".to_string(),
+            2,
+            "a\n    b\r\n\t\tc\n \t d\n\t \r  ".to_string(),
+        );
+        let cases = [
+            (0, Some((0, IndentKind::None))),
+            (1, Some((4, IndentKind::Spaces))),
+            (2, Some((8, IndentKind::Tabs))),
+            (3, Some((5, IndentKind::Mixed))),
+            (4, Some((5, IndentKind::Mixed))),
+            (5, Some((2, IndentKind::Spaces))),
+            (6, None),
+        ];
+        for (line, indentation) in cases {
+            assert_eq!(
+                text_document.line_indentation(line, 4),
+                indentation,
+                "line {line}"
+            );
+        }
+        assert_eq!(
+            text_document.line_indentation(3, 2),
+            Some((3, IndentKind::Mixed))
+        );
+    }
+
+    #[test]
+    fn test_trailing_whitespace_ranges() {
+        let text_document = FullTextDocument::new(
+            "js".to_string(),
+            2,
+            "a \n\u{10437}\t \r\nb\n  \rc \t".to_string(),
+        );
+        assert_eq!(
+            text_document.trailing_whitespace_range(1),
+            Some(Range::new(Position::new(1, 2), Position::new(1, 4)))
+        );
+        assert_eq!(text_document.trailing_whitespace_range(2), None);
+        assert_eq!(text_document.trailing_whitespace_range(5), None);
+        assert_eq!(
+            text_document.trailing_whitespace_ranges(),
+            vec![
+                Range::new(Position::new(0, 1), Position::new(0, 2)),
+                Range::new(Position::new(1, 2), Position::new(1, 4)),
+                Range::new(Position::new(3, 0), Position::new(3, 2)),
+                Range::new(Position::new(4, 1), Position::new(4, 3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_line_range() {
+        let text_document = full_text_document();
+        assert_eq!(
+            text_document.line_range(0),
+            Some(Range::new(Position::new(0, 0), Position::new(0, 2)))
+        );
+        assert_eq!(
+            text_document.line_range(2),
+            Some(Range::new(Position::new(2, 0), Position::new(2, 5)))
+        );
+        assert_eq!(
+            text_document.line_range(4),
+            Some(Range::new(Position::new(4, 0), Position::new(4, 3)))
+        );
+        assert_eq!(text_document.line_range(5), None);
+
+        let text_document =
+            FullTextDocument::new("js".to_string(), 2, "\u{20AC}\u{10437}\n".to_string());
+        assert_eq!(
+            text_document.line_range(0),
+            Some(Range::new(Position::new(0, 0), Position::new(0, 3)))
+        );
+        assert_eq!(
+            text_document.line_range(1),
+            Some(Range::new(Position::new(1, 0), Position::new(1, 0)))
+        );
+
+        let text_document = FullTextDocument::with_encoding(
+            "js".to_string(),
+            2,
+            "\u{20AC}\u{10437}\n".to_string(),
+            PositionEncoding::Utf8,
+        );
+        assert_eq!(
+            text_document.line_range(0),
+            Some(Range::new(Position::new(0, 0), Position::new(0, 7)))
+        );
+    }
+
+    #[test]
+    fn test_line_len_utf16() {
+        let text_document =
+            FullTextDocument::new("js".to_string(), 2, "\u{10437} yee\r\n\u{20AC}".to_string());
+        assert_eq!(text_document.line_len_utf16(0), Some(6));
+        assert_eq!(text_document.line_len_utf16(1), Some(1));
+        assert_eq!(text_document.line_len_utf16(2), None);
+    }
+
+    #[test]
+    fn test_line_len_utf16_cached() {
+        let mut random = Random(5);
+        let mut text_document =
+            FullTextDocument::new("js".to_string(), 0, "ab\r\n\u{10437}\ncd".to_string());
+        text_document.set_line_len_cache(true);
+        text_document.set_history_depth(5);
+        for version in 1..300 {
+            // every line is cached before the edit
+            for line in 0..text_document.line_count() {
+                text_document.line_len_utf16_cached(line);
+            }
+
+            if version % 50 == 0 {
+                assert!(text_document.undo());
+            } else {
+                let text = random.text(4);
+                let range = (random.below(20) != 0).then(|| {
+                    let content_len = text_document.content_len();
+                    let start = random.below(content_len + 1);
+                    let end = start + random.below(content_len - start + 1).min(4);
+                    Range::new(
+                        text_document.position_at(start),
+                        text_document.position_at(end),
+                    )
+                });
+                let change = TextDocumentContentChangeEvent {
+                    range,
+                    range_length: None,
+                    text,
+                };
+                text_document.update(&[change], version);
+            }
+
+            for line in 0..=text_document.line_count() {
+                assert_eq!(
+                    text_document.line_len_utf16_cached(line),
+                    text_document.line_len_utf16(line),
+                    "line {line} of {:?}",
+                    text_document.content
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_content() {
+        let text_document = full_text_document();
+
+        let start = Position {
+            line: 0,
+            character: 0,
+        };
+        let end = Position {
+            line: 1,
+            character: 2,
+        };
+        let range = Range { start, end };
+        let content = text_document.get_content(Some(range));
+        assert_eq!(content, "he\nll");
+
+        let end = Position {
+            line: 100,
+            character: 100,
+        };
+        let range = Range { start, end };
+        let content = text_document.get_content(Some(range));
+        assert_eq!(content, text_document.content);
+
+        let range = Range {
+            start: Position {
+                line: 1,
+                character: 0,
+            },
+            end: Position {
+                line: 2,
+                character: 3,
+            },
+        };
+        let content = text_document.get_content(Some(range));
+        assert_eq!(content, "llo\nwor");
+    }
+
+    #[test]
+    fn test_get_content_owned() {
+        let text_document = full_text_document();
+        let range = Range::new(Position::new(1, 1), Position::new(3, 1));
+        let content = text_document.get_content_owned(Some(range));
+        drop(text_document);
+        assert_eq!(content, "lo\nworld\r\nf");
+    }
+
+    #[test]
+    fn test_get_content_inverted_range() {
+        let text_document = full_text_document();
+        let range = Range::new(Position::new(2, 3), Position::new(1, 1));
+        assert_eq!(text_document.try_get_content(Some(range)), None);
+        assert_eq!(text_document.get_content(Some(range)), "");
+    }
+
+    #[test]
+    fn test_get_content_bytes() {
+        let text_document =
+            FullTextDocument::new("js".to_string(), 2, "a\u{10437}\r\nb".to_string());
+        let len = text_document.content.len();
+        let cases = [
+            (0..len, Some("a\u{10437}\r\nb")),
+            (0..0, Some("")),
+            (len..len, Some("")),
+            // inside U+10437
+            (2..4, Some("")),
+            (1..6, Some("\u{10437}\r")),
+            (3..7, Some("\u{10437}\r\n")),
+            (std::ops::Range { start: 3, end: 2 }, None),
+            (0..len + 1, None),
+            (usize::MAX..usize::MAX, None),
+        ];
+        for (range, expected) in cases {
+            assert_eq!(
+                text_document.get_content_bytes(range.clone()),
+                expected,
+                "{range:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_content_out_of_bounds() {
+        let text_document = full_text_document();
+        // both ends beyond the end of the document
+        let range = Range::new(Position::new(100, 0), Position::new(100, 100));
+        assert_eq!(text_document.get_content(Some(range)), "");
+        assert_eq!(text_document.try_get_content(Some(range)), Some(""));
+        let range = Range::new(Position::new(4, 100), Position::new(u32::MAX, u32::MAX));
+        assert_eq!(text_document.get_content(Some(range)), "");
+
+        // inverted, beyond the end and before it
+        let range = Range::new(Position::new(100, 100), Position::new(0, 1));
+        assert_eq!(text_document.get_content(Some(range)), "");
+        assert_eq!(text_document.try_get_content(Some(range)), None);
+        let normalized = text_document.normalize_range(range);
+        assert_eq!(
+            text_document.get_content(Some(normalized)),
+            &text_document.content[1..]
+        );
+    }
+
+    /// positions inside a multi-byte character round down to a char boundary
+    #[test]
+    fn test_get_content_mid_character() {
+        let text_document = FullTextDocument::with_encoding(
+            "js".to_string(),
+            2,
+            "a\u{20AC}b\u{10437}c".to_string(),
+            PositionEncoding::Utf8,
+        );
+        // both ends inside a character
+        let range = Range::new(Position::new(0, 2), Position::new(0, 6));
+        assert_eq!(text_document.get_content(Some(range)), "\u{20AC}b");
+
+        // the end inside the same character as the start
+        let range = Range::new(Position::new(0, 2), Position::new(0, 3));
+        assert_eq!(text_document.get_content(Some(range)), "");
+
+        // UTF-16 columns inside a surrogate pair
+        let text_document = FullTextDocument::new("js".to_string(), 2, "a\u{10437}c".to_string());
+        let range = Range::new(Position::new(0, 2), Position::new(0, 4));
+        assert_eq!(text_document.get_content(Some(range)), "\u{10437}c");
+    }
+
+    /// basic multilingual plane
+    #[test]
+    fn test_get_content_bmp() {
+        // Euro symbol
+        let text_document = FullTextDocument::new("js".to_string(), 2, "\u{20AC} euro".to_string());
+
+        // Euro symbol is 1 UTF16 code unit wide
+        let range = Range {
+            start: Position {
+                line: 0,
+                character: 0,
+            },
+            end: Position {
+                line: 0,
+                character: 1,
+            },
+        };
+        let content = text_document.get_content(Some(range));
+        assert_eq!(content, "\u{20AC}");
+
+        // E euro
+        //   ^
+        let range = Range {
+            start: Position {
+                line: 0,
+                character: 2,
+            },
+            end: Position {
+                line: 0,
+                character: 3,
+            },
+        };
+        let content = text_document.get_content(Some(range));
+        assert_eq!(content, "e");
+    }
+
+    /// supplementary multilingual plane, aka surrogate pairs
+    #[test]
+    fn test_get_content_smp() {
+        // Deseret Small Letter Yee
+        let text_document = FullTextDocument::new("js".to_string(), 2, "\u{10437} yee".to_string());
+
+        // surrogate pairs are 2 UTF16 code units wide
+        let range = Range {
+            start: Position {
+                line: 0,
+                character: 0,
+            },
+            end: Position {
+                line: 0,
+                character: 2,
+            },
+        };
+        let content = text_document.get_content(Some(range));
+        assert_eq!(content, "\u{10437}");
+    }
+
+    #[test]
+    fn test_try_update_invalid_range() {
+        let mut text_document = full_text_document();
+        let range = Range {
+            start: Position {
+                line: 2,
+                character: 0,
+            },
+            end: Position {
+                line: 1,
+                character: 0,
+            },
+        };
+        let result = text_document.try_update(
+            &[TextDocumentContentChangeEvent {
+                text: String::from(""),
+                range: Some(range),
+                range_length: Some(0),
+            }],
+            3,
+        );
+        assert_eq!(
+            result,
+            Err(UpdateError::InvertedRange {
+                start: range.start,
+                start_offset: 7,
+                end: range.end,
+                end_offset: 3,
+            })
+        );
+        assert_eq!(text_document.version(), 2);
+    }
+
+    #[test]
+    fn test_try_update_stale_version() {
+        let mut text_document = full_text_document();
+        let result = text_document.try_update(
+            &[TextDocumentContentChangeEvent {
+                text: String::from("stale"),
+                range: None,
+                range_length: None,
+            }],
+            1,
+        );
+        assert_eq!(
+            result,
+            Err(UpdateError::StaleVersion {
+                current: 2,
+                received: 1,
+            })
+        );
+        assert_eq!(
+            text_document.get_content(None),
+            "he\nllo\nworld\r\nfoo\rbar"
+        );
+    }
+
+    #[test]
+    fn test_try_update_same_version() {
+        let mut text_document = full_text_document();
+        assert_eq!(text_document.expects_version(), 3);
+        let changes = [TextDocumentContentChangeEvent {
+            text: String::from("stale"),
+            range: None,
+            range_length: None,
+        }];
+
+        let result = text_document.try_update(&changes, 2);
+        assert_eq!(
+            result,
+            Err(UpdateError::StaleVersion {
+                current: 2,
+                received: 2,
+            })
+        );
+
+        text_document.set_stale_version_policy(StaleVersionPolicy::Skip);
+        assert_eq!(text_document.try_update(&changes, 2), Ok(()));
+        assert_eq!(
+            text_document.get_content(None),
+            "he\nllo\nworld\r\nfoo\rbar"
+        );
+
+        assert_eq!(text_document.try_update(&changes, 3), Ok(()));
+        assert_eq!(text_document.get_content(None), "stale");
+        assert_eq!(text_document.expects_version(), 4);
+    }
+
+    /// Appending with a position on a line beyond the document must not index
+    /// `line_offsets` out of bounds.
+    #[test]
+    fn test_update_skips_changes_before_full_text() {
+        let mut text_document = full_text_document();
+        let changes = [
+            TextDocumentContentChangeEvent {
+                // inverted, but overridden by the full replacement
+                range: Some(Range::new(Position::new(1, 0), Position::new(0, 0))),
+                range_length: None,
+                text: "ignored".to_string(),
+            },
+            TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: "hello\nrust".to_string(),
+            },
+            TextDocumentContentChangeEvent {
+                range: Some(Range::new(Position::new(1, 4), Position::new(1, 4))),
+                range_length: None,
+                text: "!".to_string(),
+            },
+        ];
+        assert_eq!(text_document.try_update(&changes, 3), Ok(()));
+        assert_eq!(text_document.get_content(None), "hello\nrust!");
+        assert_eq!(text_document.line_offsets(), &[0, 6]);
+        assert_eq!(text_document.version(), 3);
+    }
+
+    #[test]
+    fn test_shrink_to_fit() {
+        let content = "hello rust!\n".repeat(1000);
+        let mut text_document = FullTextDocument::new("text".to_string(), 0, content);
+        text_document.set_history_depth(1);
+        text_document.update(
+            &[TextDocumentContentChangeEvent {
+                range: Some(Range::new(Position::new(1, 0), Position::new(1000, 0))),
+                range_length: None,
+                text: String::new(),
+            }],
+            1,
+        );
+        assert!(text_document.content.capacity() >= 12000);
+
+        text_document.shrink_to_fit();
+        assert_eq!(text_document.content.capacity(), 12);
+        assert_eq!(text_document.line_offsets.capacity(), 2);
+        text_document.debug_assert_invariants();
+
+        assert!(text_document.undo());
+        assert_eq!(text_document.line_count(), 1001);
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut text_document = full_text_document();
+        let insert = |text: &str| TextDocumentContentChangeEvent {
+            range: Some(Range::new(Position::new(0, 0), Position::new(0, 0))),
+            range_length: None,
+            text: text.to_string(),
+        };
+
+        // disabled by default
+        text_document.update(&[insert("a")], 3);
+        assert!(!text_document.undo());
+
+        text_document.set_history_depth(2);
+        text_document.update(&[insert("b")], 4);
+        text_document.update(&[insert("c\n")], 5);
+        text_document.update(&[insert("d")], 6);
+        assert_eq!(
+            text_document.get_content(None),
+            "dc\nbahe\nllo\nworld\r\nfoo\rbar"
+        );
+
+        assert!(text_document.undo());
+        assert_eq!(
+            text_document.get_content(None),
+            "c\nbahe\nllo\nworld\r\nfoo\rbar"
+        );
+        assert_eq!(text_document.version(), 5);
+        assert!(text_document.undo());
+        assert_eq!(
+            text_document.get_content(None),
+            "bahe\nllo\nworld\r\nfoo\rbar"
+        );
+        assert_eq!(text_document.line_offsets(), &[0, 5, 9, 16, 20]);
+        assert_eq!(text_document.version(), 4);
+        // only the last 2 updates are kept
+        assert!(!text_document.undo());
+
+        assert!(text_document.redo());
+        assert_eq!(
+            text_document.get_content(None),
+            "c\nbahe\nllo\nworld\r\nfoo\rbar"
+        );
+        assert_eq!(text_document.line_offsets(), &[0, 2, 7, 11, 18, 22]);
+        assert_eq!(text_document.version(), 5);
+
+        // an update discards the undone updates
+        text_document.update(&[insert("e")], 6);
+        assert!(!text_document.redo());
+        assert!(text_document.undo());
+        assert_eq!(
+            text_document.get_content(None),
+            "c\nbahe\nllo\nworld\r\nfoo\rbar"
+        );
+
+        // a rejected update isn't recorded
+        let inverted = TextDocumentContentChangeEvent {
+            range: Some(Range::new(Position::new(1, 0), Position::new(0, 0))),
+            range_length: None,
+            text: String::new(),
+        };
+        assert!(text_document.try_update(&[inverted], 7).is_err());
+        assert!(text_document.undo());
+        assert_eq!(
+            text_document.get_content(None),
+            "bahe\nllo\nworld\r\nfoo\rbar"
+        );
+    }
+
+    #[test]
+    fn test_update_full_text_unchanged() {
+        let mut text_document = full_text_document();
+        let content_ptr = text_document.content.as_ptr();
+        let line_offsets_ptr = text_document.line_offsets.as_ptr();
+        text_document.update(
+            &[TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: "he\nllo\nworld\r\nfoo\rbar".to_string(),
+            }],
+            3,
+        );
+        assert_eq!(text_document.version(), 3);
+        assert_eq!(text_document.content.as_ptr(), content_ptr);
+        assert_eq!(text_document.line_offsets.as_ptr(), line_offsets_ptr);
+        assert_eq!(text_document.line_offsets(), &[0, 3, 7, 14, 18]);
     }
 
-    /// A amount of document content line
-    pub fn line_count(&self) -> u32 {
-        self.line_offsets
-            .len()
-            .try_into()
-            .expect("The number of lines of text passed in is too long")
-    }
+    /// the line right after the last line is where a client appends text
+    #[test]
+    fn test_update_append_at_line_count() {
+        for content in ["abc", "abc\n", "abc\r\n", "abc\r", ""] {
+            let mut text_document =
+                FullTextDocument::new("text".to_string(), 0, content.to_string());
+            let position = Position::new(text_document.line_count(), 0);
+            assert_eq!(
+                text_document.find_canonical_position(&position),
+                (text_document.end_position(), content.len() as u32)
+            );
 
-    /// The length of the document content in UTF-8 bytes
-    pub fn content_len(&self) -> u32 {
-        self.content
-            .len()
-            .try_into()
-            .expect("The length of the text passed in is too long")
+            text_document.update(
+                &[TextDocumentContentChangeEvent {
+                    text: String::from("x\n"),
+                    range: Some(Range::new(position, position)),
+                    range_length: None,
+                }],
+                1,
+            );
+            assert_eq!(text_document.get_content(None), format!("{content}x\n"));
+            text_document.debug_assert_invariants();
+        }
     }
 
-    /// Converts a zero-based byte offset in the UTF8-encoded content to a position
-    ///
-    /// the offset is in bytes, the position is in UTF16 code units. rounds down if
-    /// the offset is not on a code unit boundary, or is beyond the end of the
-    /// content.
-    pub fn position_at(&self, offset: u32) -> Position {
-        let offset = offset.min(self.content_len());
-        let line_count = self.line_count();
-        if line_count == 1 {
-            // only one line
-            return Position {
-                line: 0,
-                character: line_offset_utf16(self.get_line(0).unwrap(), offset),
-            };
-        }
+    #[test]
+    fn test_update_at_huge_character() {
+        for (content, expected) in [
+            ("abc\ndef", "aX\ndef"),
+            ("abc\r\ndef", "aX\r\ndef"),
+            ("a\u{10437}c\rdef", "aX\rdef"),
+        ] {
+            let mut text_document =
+                FullTextDocument::new("text".to_string(), 0, content.to_string());
+            let end = Position::new(0, u32::MAX);
+            text_document.update(
+                &[TextDocumentContentChangeEvent {
+                    text: String::from("X"),
+                    range: Some(Range::new(Position::new(0, 1), end)),
+                    range_length: None,
+                }],
+                1,
+            );
+            assert_eq!(text_document.get_content(None), expected);
+            text_document.debug_assert_invariants();
 
-        let (mut low, mut high) = (0, line_count);
-        while low < high {
-            let mid = (low + high) / 2;
-            if offset
-                > *self
-                    .line_offsets
-                    .get(mid as usize)
-                    .expect("Unknown mid value")
-            {
-                low = mid + 1;
-            } else {
-                high = mid;
-            }
+            // inserting at the end of the line
+            text_document.update(
+                &[TextDocumentContentChangeEvent {
+                    text: String::from("!"),
+                    range: Some(Range::new(end, end)),
+                    range_length: None,
+                }],
+                2,
+            );
+            assert_eq!(text_document.line(0), Some("aX!"));
+            assert_eq!(text_document.line(1), Some("def"));
         }
 
-        if low == 0 {
-            // offset is on the first line
-            return Position {
-                line: 0,
-                character: line_offset_utf16(self.get_line(0).unwrap(), offset),
-            };
+        // the last line has no terminator
+        let mut text_document = FullTextDocument::new("text".to_string(), 0, "abc".to_string());
+        let end = Position::new(0, u32::MAX);
+        let range = text_document.splice(Range::new(end, end), "!").unwrap();
+        assert_eq!(text_document.get_content(None), "abc!");
+        assert_eq!(range, Position::new(0, 4));
+
+        // right after the `\r`, after the `\r\n` and beyond it, never splitting it
+        for character in [3, 4, 5, u32::MAX] {
+            let mut text_document =
+                FullTextDocument::new("text".to_string(), 0, "ab\r\ncd".to_string());
+            let position = Position::new(0, character);
+            assert_eq!(
+                text_document.find_canonical_position(&position),
+                (Position::new(0, 2), 2)
+            );
+            let end = text_document
+                .splice(Range::new(position, position), "x")
+                .unwrap();
+            assert_eq!(text_document.get_content(None), "abx\r\ncd", "{character}");
+            assert_eq!(end, Position::new(0, 3));
         }
+    }
 
-        let line = low - 1;
+    #[test]
+    fn test_update_append_beyond_end_of_document() {
+        let mut text_document =
+            FullTextDocument::new("text".to_string(), 0, "123456789\nabc".to_string());
+        let position = Position {
+            line: 5,
+            character: 0,
+        };
+        text_document.update(
+            &[TextDocumentContentChangeEvent {
+                text: String::from("\nx"),
+                range: Some(Range::new(position, position)),
+                range_length: None,
+            }],
+            1,
+        );
+        assert_eq!(text_document.get_content(None), "123456789\nabc\nx");
+        assert_eq!(text_document.line_offsets, vec![0, 10, 14]);
 
-        Position {
-            line,
-            character: line_offset_utf16(
-                self.get_line(line).unwrap(),
-                offset - self.line_offsets[line as usize],
-            ),
-        }
+        let mut text_document =
+            FullTextDocument::new("text".to_string(), 0, "123456789\n".to_string());
+        let result = text_document.try_update(
+            &[TextDocumentContentChangeEvent {
+                text: String::from("x"),
+                range: Some(Range::new(position, position)),
+                range_length: None,
+            }],
+            1,
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(text_document.get_content(None), "123456789\nx");
+        assert_eq!(text_document.line_offsets, vec![0, 10]);
     }
 
-    /// Converts a position to a zero-based byte offset, suitable for slicing the
-    /// UTF-8 encoded content.
-    pub fn offset_at(&self, position: Position) -> u32 {
-        let Position { line, character } = position;
-        match self.get_line_and_offset(line) {
-            Some((line, offset)) => {
-                let mut c = 0;
-                let iter = line.char_indices();
-                for (idx, char) in iter {
-                    if c == character {
-                        return offset + idx as u32;
-                    }
-                    c += char.len_utf16() as u32;
-                }
-                offset + line.len() as u32
-            }
-            None => {
-                if line >= self.line_count() {
-                    self.content_len()
-                } else {
-                    0
-                }
-            }
+    #[test]
+    fn test_find_all() {
+        let text_document = FullTextDocument::new(
+            "js".to_string(),
+            2,
+            "\u{10437}foo\nFOO\r\nfoo\nfo".to_string(),
+        );
+        let ranges = text_document.find_all("foo");
+        assert_eq!(
+            ranges,
+            vec![
+                Range::new(Position::new(0, 2), Position::new(0, 5)),
+                Range::new(Position::new(2, 0), Position::new(2, 3)),
+            ]
+        );
+        for range in ranges {
+            assert_eq!(text_document.get_content(Some(range)), "foo");
         }
+
+        // matches spanning line boundaries
+        let ranges = text_document.find_all("o\nf");
+        assert_eq!(
+            ranges,
+            vec![Range::new(Position::new(2, 2), Position::new(3, 1))]
+        );
+        assert_eq!(text_document.get_content(Some(ranges[0])), "o\nf");
+
+        assert_eq!(text_document.find_all(""), vec![]);
+        assert_eq!(text_document.find_all_ignore_ascii_case("fOo").len(), 3);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_find_from() {
+        let text_document = FullTextDocument::new(
+            "js".to_string(),
+            2,
+            "\u{10437}foo\nFOO\r\nfoo\nfoofoo".to_string(),
+        );
+        let find_from = |line, character, wrap| {
+            text_document.find_from("foo", Position::new(line, character), wrap)
+        };
+        let at = |line, character| {
+            Some(Range::new(
+                Position::new(line, character),
+                Position::new(line, character + 3),
+            ))
+        };
 
-    fn full_text_document() -> FullTextDocument {
-        FullTextDocument::new(
+        assert_eq!(find_from(0, 0, false), at(0, 2));
+        // strictly after, also inside a surrogate pair
+        assert_eq!(find_from(0, 1, false), at(0, 2));
+        assert_eq!(find_from(0, 2, false), at(2, 0));
+        assert_eq!(find_from(2, 0, false), at(3, 0));
+        assert_eq!(find_from(3, 0, false), at(3, 3));
+        assert_eq!(find_from(3, 3, false), None);
+        assert_eq!(find_from(3, 3, true), at(0, 2));
+        assert_eq!(find_from(100, 0, true), at(0, 2));
+
+        assert_eq!(
+            text_document.find_from("bar", Position::new(0, 0), true),
+            None
+        );
+        assert_eq!(text_document.find_from("", Position::new(0, 0), true), None);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_find_regex() {
+        let text_document = FullTextDocument::new(
             "js".to_string(),
             2,
-            "he\nllo\nworld\r\nfoo\rbar".to_string(),
-        )
+            "\u{10437} TODO\r\n\u{20AC} TODO\nTODO".to_string(),
+        );
+        let regex = regex::Regex::new(r"TODO\s+\S").unwrap();
+        let ranges = text_document.find_regex(&regex);
+        assert_eq!(
+            ranges,
+            vec![
+                Range::new(Position::new(0, 3), Position::new(1, 1)),
+                Range::new(Position::new(1, 2), Position::new(2, 1)),
+            ]
+        );
+        assert_eq!(
+            text_document.get_content(Some(ranges[0])),
+            "TODO\r\n\u{20AC}"
+        );
     }
 
     #[test]
-    fn test_offset_at() {
+    fn test_byte_range_to_range() {
         let text_document = full_text_document();
+        let cases = [
+            (0..2, Range::new(Position::new(0, 0), Position::new(0, 2))),
+            (3..7, Range::new(Position::new(1, 0), Position::new(2, 0))),
+            (12..14, Range::new(Position::new(2, 5), Position::new(3, 0))),
+            (18..21, Range::new(Position::new(4, 0), Position::new(4, 3))),
+        ];
+        for (bytes, range) in cases {
+            assert_eq!(text_document.byte_range_to_range(bytes.clone()), range);
+            assert_eq!(text_document.range_to_byte_range(range), bytes);
+        }
 
-        let offset = text_document.offset_at(Position {
-            line: 1,
-            character: 1,
-        });
-        assert_eq!(offset, 4);
+        let text_document =
+            FullTextDocument::new("js".to_string(), 2, "\u{20AC}\u{10437}\nx".to_string());
+        let range = Range::new(Position::new(0, 1), Position::new(1, 0));
+        assert_eq!(text_document.byte_range_to_range(3..8), range);
+        assert_eq!(text_document.range_to_byte_range(range), 3..8);
+        // between the surrogates of U+10437
+        assert_eq!(
+            text_document.range_to_byte_range(Range::new(Position::new(0, 2), Position::new(0, 3))),
+            3..7
+        );
+    }
 
-        let offset = text_document.offset_at(Position {
-            line: 2,
-            character: 3,
-        });
-        assert_eq!(offset, 10);
+    #[test]
+    fn test_apply_text_edits() {
+        let mut text_document = full_text_document();
+        let edits = [
+            TextEdit::new(
+                Range::new(Position::new(3, 0), Position::new(4, 0)),
+                String::new(),
+            ),
+            TextEdit::new(
+                Range::new(Position::new(0, 0), Position::new(0, 0)),
+                "1".to_string(),
+            ),
+            TextEdit::new(
+                Range::new(Position::new(1, 3), Position::new(2, 0)),
+                "\r\n".to_string(),
+            ),
+            TextEdit::new(
+                Range::new(Position::new(0, 0), Position::new(0, 0)),
+                "2".to_string(),
+            ),
+        ];
+        text_document.apply_text_edits(&edits).unwrap();
+        assert_eq!(text_document.get_content(None), "12he\nllo\r\nworld\r\nbar");
+        assert_eq!(text_document.line_offsets(), &[0, 5, 10, 17]);
+        assert_eq!(text_document.version(), 2);
 
-        // the `f` in `foo` (\r\n is a single line terminator)
-        let offset = text_document.offset_at(Position {
-            line: 3,
-            character: 1,
-        });
-        assert_eq!(offset, 15);
+        // the diff of two texts turns one into the other
+        let mut text_document = full_text_document();
+        let new_text = "he\nl\u{10437}o\rworld\r\nbar\n";
+        let edits = text_document.diff(new_text);
+        text_document.apply_text_edits(&edits).unwrap();
+        assert_eq!(text_document.get_content(None), new_text);
     }
 
-    /// basic multilingual plane
     #[test]
-    fn test_offset_at_bmp() {
-        // Euro symbol
-        let text_document = FullTextDocument::new("js".to_string(), 2, "\u{20AC} euro".to_string());
-
-        let offset = text_document.offset_at(Position {
-            line: 0,
-            // E euro
-            //   ^
-            character: 2,
-        });
-        assert_eq!(offset, 4);
+    fn test_apply_text_edits_overlapping() {
+        let mut text_document = full_text_document();
+        let first = Range::new(Position::new(0, 1), Position::new(1, 1));
+        let second = Range::new(Position::new(1, 0), Position::new(1, 2));
+        let edits = [
+            TextEdit::new(
+                Range::new(Position::new(3, 0), Position::new(3, 0)),
+                "x".to_string(),
+            ),
+            TextEdit::new(second, String::new()),
+            TextEdit::new(first, String::new()),
+        ];
+        assert_eq!(
+            text_document.apply_text_edits(&edits),
+            Err(UpdateError::OverlappingEdits { first, second })
+        );
+        // nothing is applied
+        assert_eq!(text_document, full_text_document());
     }
 
-    /// supplementary multilingual plane, aka surrogate pair
     #[test]
-    fn test_offset_at_smp() {
-        // Deseret Small Letter Yee
-        let text_document = FullTextDocument::new("js".to_string(), 2, "\u{10437} yee".to_string());
-        let offset = text_document.offset_at(Position {
-            line: 0,
-            // HL yee
-            //    ^
-            character: 3,
-        });
-        assert_eq!(offset, 5);
+    fn test_apply_disjoint_changes() {
+        let mut text_document = full_text_document();
+        let change =
+            |start: (u32, u32), end: (u32, u32), text: &str| TextDocumentContentChangeEvent {
+                range: Some(Range::new(
+                    Position::new(start.0, start.1),
+                    Position::new(end.0, end.1),
+                )),
+                range_length: None,
+                text: text.to_string(),
+            };
+        let changes = [
+            change((4, 0), (4, 3), "baz"),
+            change((0, 2), (1, 0), "\r"),
+            change((2, 5), (2, 5), ";"),
+        ];
+        assert_eq!(text_document.apply_disjoint_changes(&changes), Ok(()));
+        assert_eq!(
+            text_document.get_content(None),
+            "he\rllo\nworld;\r\nfoo\rbaz"
+        );
+        assert_eq!(text_document.line_offsets(), &[0, 3, 7, 15, 19]);
+        assert_eq!(text_document.version(), 2);
+
+        // all or nothing
+        let changes = [
+            change((0, 0), (0, 1), "H"),
+            change((2, 1), (2, 3), ""),
+            change((2, 2), (2, 4), ""),
+        ];
+        assert!(matches!(
+            text_document.apply_disjoint_changes(&changes),
+            Err(UpdateError::OverlappingEdits { .. })
+        ));
+        assert_eq!(
+            text_document.get_content(None),
+            "he\rllo\nworld;\r\nfoo\rbaz"
+        );
+
+        text_document.set_sync_kind(TextDocumentSyncKind::FULL);
+        assert!(matches!(
+            text_document.apply_disjoint_changes(&changes[..1]),
+            Err(UpdateError::UnexpectedIncrementalChange { .. })
+        ));
     }
 
-    /// a character beyond the end of the line should clamp to the end of the line
     #[test]
-    fn test_offset_at_beyond_end_of_line() {
+    fn test_diff() {
+        let text_document = full_text_document();
+        assert_eq!(text_document.diff("he\nllo\nworld\r\nfoo\rbar"), vec![]);
+
+        let new_texts = [
+            "he\nllo\r\nworld\nfoo\rbar",
+            "\u{10437}he\nllo\nw\u{20AC}rld\r\nfoo\rbar\n",
+            "foo\rbar",
+            "",
+        ];
+        for new_text in new_texts {
+            let mut updated = text_document.clone();
+            let edits = text_document.diff(new_text);
+            for edit in edits.into_iter().rev() {
+                updated.update(
+                    &[TextDocumentContentChangeEvent {
+                        range: Some(edit.range),
+                        range_length: None,
+                        text: edit.new_text,
+                    }],
+                    3,
+                );
+            }
+            assert_eq!(updated.get_content(None), new_text);
+        }
+
         let text_document =
-            FullTextDocument::new("js".to_string(), 2, "\u{20AC} abc\nline 2".to_string());
-        // "\u{20AC} abc\nline 2" in UTF-8:
-        // \xE2 \x82 \xAC \x20 \x61 \x62 \x63 \x0A \x6C \x69 \x6E \x65 \x20 \x32
-        // ^ line 1 == 0                           ^ line 2 == 8
-        assert_eq!(text_document.line_offsets, vec![0, 8]);
+            FullTextDocument::new("js".to_string(), 2, "\u{10437}a\r\nb".to_string());
+        assert_eq!(
+            text_document.diff("\u{10437}x\r\nb"),
+            vec![TextEdit::new(
+                Range::new(Position::new(0, 2), Position::new(0, 3)),
+                "x".to_string()
+            )]
+        );
+    }
 
-        let offset = text_document.offset_at(Position {
-            line: 0,
-            character: 100,
-        });
-        assert_eq!(offset, 8);
+    #[test]
+    fn test_changes_to() {
+        let mut random = Random(7);
+        for _ in 0..2000 {
+            let (old_text, new_text) = (random.text(12), random.text(12));
+            let mut text_document = FullTextDocument::new("js".to_string(), 1, old_text.clone());
+            let changes = text_document.changes_to(&new_text);
+            for change in &changes {
+                let range = change.range.unwrap();
+                assert!(
+                    text_document.offset_at(range.start) <= text_document.offset_at(range.end),
+                    "{old_text:?} -> {new_text:?}"
+                );
+            }
+            text_document.update(&changes, 2);
+            assert_eq!(
+                text_document.get_content(None),
+                new_text,
+                "{old_text:?} -> {new_text:?}"
+            );
+        }
     }
 
     #[test]
-    fn test_position_at() {
+    fn test_char_at() {
         let text_document = full_text_document();
+        assert_eq!(text_document.char_at(Position::new(0, 0)), Some('h'));
+        assert_eq!(text_document.char_at(Position::new(0, 1)), Some('e'));
+        assert_eq!(text_document.char_at(Position::new(0, 2)), None);
+        assert_eq!(text_document.char_at(Position::new(2, 4)), Some('d'));
+        assert_eq!(text_document.char_at(Position::new(2, 5)), None);
+        assert_eq!(text_document.char_at(Position::new(4, 2)), Some('r'));
+        assert_eq!(text_document.char_at(Position::new(4, 3)), None);
+        assert_eq!(text_document.char_at(Position::new(5, 0)), None);
 
-        let position = text_document.position_at(5);
+        let text_document =
+            FullTextDocument::new("js".to_string(), 2, "\u{20AC}\u{10437}x".to_string());
+        assert_eq!(text_document.char_at(Position::new(0, 0)), Some('\u{20AC}'));
         assert_eq!(
-            position,
-            Position {
-                line: 1,
-                character: 2
-            }
+            text_document.char_at(Position::new(0, 1)),
+            Some('\u{10437}')
         );
-
-        let position = text_document.position_at(11);
         assert_eq!(
-            position,
-            Position {
-                line: 2,
-                character: 4,
-            }
+            text_document.char_at(Position::new(0, 2)),
+            Some('\u{10437}')
         );
+        assert_eq!(text_document.char_at(Position::new(0, 3)), Some('x'));
+    }
 
-        let position = text_document.position_at(15);
+    #[test]
+    fn test_text_before() {
+        let content = "ab\r\nx\u{10437}\u{20AC}y";
+        let cases = [
+            (PositionEncoding::Utf16, (1, 5), 2, "\u{20AC}y"),
+            // the surrogate pair doesn't fit
+            (PositionEncoding::Utf16, (1, 5), 3, "\u{20AC}y"),
+            (PositionEncoding::Utf16, (1, 5), 4, "\u{10437}\u{20AC}y"),
+            (PositionEncoding::Utf8, (1, 9), 4, "\u{20AC}y"),
+            (PositionEncoding::Utf8, (1, 9), 8, "\u{10437}\u{20AC}y"),
+            (PositionEncoding::Utf32, (1, 4), 3, "\u{10437}\u{20AC}y"),
+            // never the previous line
+            (PositionEncoding::Utf16, (1, 1), 9, "x"),
+            (PositionEncoding::Utf16, (0, 9), 9, "ab"),
+            (PositionEncoding::Utf16, (0, 2), 0, ""),
+            (PositionEncoding::Utf16, (2, 0), 9, ""),
+        ];
+        for (encoding, (line, character), max_len, expected) in cases {
+            let text_document = FullTextDocument::with_encoding(
+                "text".to_string(),
+                0,
+                content.to_string(),
+                encoding,
+            );
+            let position = Position::new(line, character);
+            assert_eq!(
+                text_document.text_before(position, max_len),
+                expected,
+                "{position:?} {max_len} in {encoding:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_char_before_and_after() {
+        let text_document =
+            FullTextDocument::new("js".to_string(), 2, "a\u{10437}\r\n\u{20AC}\rb".to_string());
+        let cases = [
+            ((0, 0), None, Some('a')),
+            ((0, 1), Some('a'), Some('\u{10437}')),
+            // inside the surrogate pair
+            ((0, 2), Some('a'), Some('\u{10437}')),
+            ((0, 3), Some('\u{10437}'), Some('\r')),
+            ((0, 4), Some('\r'), Some('\n')),
+            ((1, 0), Some('\n'), Some('\u{20AC}')),
+            ((2, 0), Some('\r'), Some('b')),
+            ((2, 1), Some('b'), None),
+            ((9, 9), Some('b'), None),
+        ];
+        for ((line, character), before, after) in cases {
+            let position = Position::new(line, character);
+            assert_eq!(text_document.char_before(position), before, "{position:?}");
+            assert_eq!(text_document.char_after(position), after, "{position:?}");
+        }
+    }
+
+    #[cfg(feature = "unicode-segmentation")]
+    #[test]
+    fn test_grapheme_at() {
+        let text_document = FullTextDocument::new(
+            "js".to_string(),
+            2,
+            "e\u{301}\u{1F1EB}\u{1F1F7}\r\nx".to_string(),
+        );
         assert_eq!(
-            position,
-            Position {
-                line: 3,
-                character: 1,
-            }
+            text_document.grapheme_at(Position::new(0, 0)),
+            Some("e\u{301}")
         );
-
-        let position = text_document.position_at(0);
         assert_eq!(
-            position,
-            Position {
-                line: 0,
-                character: 0,
-            }
+            text_document.grapheme_at(Position::new(0, 1)),
+            Some("e\u{301}")
+        );
+        assert_eq!(
+            text_document.grapheme_at(Position::new(0, 2)),
+            Some("\u{1F1EB}\u{1F1F7}")
+        );
+        assert_eq!(
+            text_document.grapheme_at(Position::new(0, 5)),
+            Some("\u{1F1EB}\u{1F1F7}")
         );
+        assert_eq!(text_document.grapheme_at(Position::new(0, 6)), None);
+        assert_eq!(text_document.grapheme_at(Position::new(1, 0)), Some("x"));
+        assert_eq!(text_document.grapheme_at(Position::new(1, 1)), None);
     }
 
-    /// basic multilingual plane
     #[test]
-    fn test_position_at_bmp() {
-        // Euro symbol
-        let text_document = FullTextDocument::new("js".to_string(), 2, "\u{20AC} euro".to_string());
-        let position = text_document.position_at(4);
-        assert_eq!(
-            position,
-            Position {
-                line: 0,
-                // E euro
-                //   ^
-                character: 2,
-            }
+    fn test_word_at() {
+        let text_document = FullTextDocument::new(
+            "js".to_string(),
+            2,
+            "let \u{10437}yee_1 = foo;\nbar  baz".to_string(),
         );
 
-        // multi-line content
-        let text_document =
-            FullTextDocument::new("js".to_string(), 2, "\n\n\u{20AC} euro\n\n".to_string());
-        let position = text_document.position_at(6);
+        // HLyee_1
+        //   ^
+        let (range, word) = text_document
+            .word_at(Position {
+                line: 0,
+                character: 6,
+            })
+            .unwrap();
+        assert_eq!(word, "\u{10437}yee_1");
         assert_eq!(
-            position,
-            Position {
-                line: 2,
-                // E euro
-                //   ^
-                character: 2,
+            range,
+            Range {
+                start: Position {
+                    line: 0,
+                    character: 4,
+                },
+                end: Position {
+                    line: 0,
+                    character: 11,
+                },
             }
         );
+        assert_eq!(text_document.get_content(Some(range)), word);
+
+        // at the start of a word
+        let (range, word) = text_document
+            .word_at(Position {
+                line: 0,
+                character: 14,
+            })
+            .unwrap();
+        assert_eq!(word, "foo");
+        assert_eq!(range.end.character, 17);
+        // right after the end of a word
+        assert_eq!(text_document.word_at(Position::new(0, 17)), None);
+
+        // never crosses a line boundary
+        let (range, word) = text_document
+            .word_at(Position {
+                line: 1,
+                character: 1,
+            })
+            .unwrap();
+        assert_eq!(word, "bar");
+        assert_eq!(range.start, Position::new(1, 0));
+
+        // whitespace
+        assert_eq!(text_document.word_at(Position::new(1, 4)), None);
+        assert_eq!(text_document.word_at(Position::new(0, 100)), None);
+        assert_eq!(text_document.word_at(Position::new(2, 0)), None);
     }
 
-    /// supplementary multilingual plane, aka surrogate pair
     #[test]
-    fn test_position_at_smp() {
-        // Deseret Small Letter Yee
-        let text_document = FullTextDocument::new("js".to_string(), 2, "\u{10437} yee".to_string());
-        assert_eq!(
-            text_document.position_at(5),
-            Position {
-                line: 0,
-                // HL yee
-                //    ^
-                character: 3,
-            }
+    fn test_clone() {
+        let mut text_document = full_text_document();
+        let snapshot = text_document.clone();
+        text_document.update(
+            &[TextDocumentContentChangeEvent {
+                text: "hello\n js!".to_string(),
+                range: None,
+                range_length: None,
+            }],
+            3,
         );
 
-        // \u{10437} is 4 bytes wide. if not on a char boundary, round down
-        assert_eq!(
-            text_document.position_at(2),
-            Position {
-                line: 0,
-                character: 0,
-            }
+        assert_eq!(snapshot.get_content(None), "he\nllo\nworld\r\nfoo\rbar");
+        assert_eq!(snapshot.line_offsets, vec![0, 3, 7, 14, 18]);
+        assert_eq!(snapshot.version(), 2);
+    }
+
+    #[test]
+    fn test_eq() {
+        let mut incremental = full_text_document();
+        incremental.update(
+            &[TextDocumentContentChangeEvent {
+                range: Some(Range::new(Position::new(1, 0), Position::new(2, 5))),
+                range_length: None,
+                text: "rust".to_string(),
+            }],
+            3,
         );
 
-        // multi-line content
-        let text_document =
-            FullTextDocument::new("js".to_string(), 2, "\n\n\u{10437} yee\n\n".to_string());
-        let position = text_document.position_at(7);
-        assert_eq!(
-            position,
-            Position {
-                line: 2,
-                // HL yee
-                //    ^
-                character: 3,
-            }
+        let mut full = full_text_document();
+        full.update(
+            &[TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: "he\nrust\r\nfoo\rbar".to_string(),
+            }],
+            3,
         );
+        assert_eq!(incremental, full);
+
+        full.update(&[], 4);
+        assert_ne!(incremental, full);
     }
 
+    #[cfg(feature = "serde")]
     #[test]
-    fn test_get_content() {
+    fn test_serde_round_trip() {
         let text_document = full_text_document();
+        let value = serde_json::to_value(&text_document).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "languageId": "js",
+                "version": 2,
+                "content": "he\nllo\nworld\r\nfoo\rbar",
+                "positionEncoding": "utf-16",
+                "syncKind": null,
+                "hasBom": false,
+            })
+        );
 
-        let start = Position {
-            line: 0,
-            character: 0,
-        };
-        let end = Position {
-            line: 1,
-            character: 2,
-        };
-        let range = Range { start, end };
-        let content = text_document.get_content(Some(range));
-        assert_eq!(content, "he\nll");
+        let restored: FullTextDocument = serde_json::from_value(value).unwrap();
+        assert_eq!(restored, text_document);
+        assert_eq!(restored.line_offsets, text_document.line_offsets);
 
-        let end = Position {
-            line: 100,
-            character: 100,
-        };
-        let range = Range { start, end };
-        let content = text_document.get_content(Some(range));
-        assert_eq!(content, text_document.content);
+        // line offsets in the payload are ignored
+        let restored: FullTextDocument = serde_json::from_value(serde_json::json!({
+            "languageId": "js",
+            "version": 2,
+            "content": "he\nllo",
+            "lineOffsets": [0, 100],
+        }))
+        .unwrap();
+        assert_eq!(restored.line_offsets, vec![0, 3]);
+        assert_eq!(restored.position_encoding(), PositionEncoding::Utf16);
+        assert!(!restored.has_bom());
 
-        let range = Range {
-            start: Position {
-                line: 1,
-                character: 0,
-            },
-            end: Position {
-                line: 2,
-                character: 3,
-            },
-        };
-        let content = text_document.get_content(Some(range));
-        assert_eq!(content, "llo\nwor");
+        let text_document = FullTextDocument::new("js".to_string(), 2, "\u{FEFF}he".to_string());
+        let value = serde_json::to_value(&text_document).unwrap();
+        assert_eq!(value["content"], "he");
+        assert_eq!(value["hasBom"], true);
+        let restored: FullTextDocument = serde_json::from_value(value).unwrap();
+        assert!(restored.has_bom());
+        assert_eq!(restored.get_content(None), "he");
+
+        // a U+FEFF that is part of the content is kept
+        let mut text_document = FullTextDocument::new("js".to_string(), 2, "he".to_string());
+        text_document.update(
+            &[TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: "\u{FEFF}he\nllo".to_string(),
+            }],
+            3,
+        );
+        let value = serde_json::to_value(&text_document).unwrap();
+        let restored: FullTextDocument = serde_json::from_value(value).unwrap();
+        assert_eq!(restored, text_document);
+        assert_eq!(restored.line_offsets, vec![0, 6]);
+        assert!(!restored.has_bom());
+
+        let text_document =
+            FullTextDocument::new("js".to_string(), 2, "\u{FEFF}\u{FEFF}he".to_string());
+        let value = serde_json::to_value(&text_document).unwrap();
+        let restored: FullTextDocument = serde_json::from_value(value).unwrap();
+        assert_eq!(restored.get_content(None), "\u{FEFF}he");
+        assert!(restored.has_bom());
     }
 
-    /// basic multilingual plane
     #[test]
-    fn test_get_content_bmp() {
-        // Euro symbol
-        let text_document = FullTextDocument::new("js".to_string(), 2, "\u{20AC} euro".to_string());
+    fn test_apply_change() {
+        let mut text_document = full_text_document();
+        let range = text_document
+            .apply_change(&TextDocumentContentChangeEvent {
+                range: Some(Range::new(Position::new(1, 1), Position::new(2, 2))),
+                range_length: None,
+                text: "\u{10437}\r\nxx\ny".to_string(),
+            })
+            .unwrap();
+        assert_eq!(
+            text_document.get_content(None),
+            "he\nl\u{10437}\r\nxx\nyrld\r\nfoo\rbar"
+        );
+        assert_eq!(range, Range::new(Position::new(1, 1), Position::new(3, 1)));
+        assert_eq!(text_document.version(), 2);
 
-        // Euro symbol is 1 UTF16 code unit wide
-        let range = Range {
-            start: Position {
-                line: 0,
-                character: 0,
-            },
-            end: Position {
-                line: 0,
-                character: 1,
-            },
-        };
-        let content = text_document.get_content(Some(range));
-        assert_eq!(content, "\u{20AC}");
+        // a full text replacement spans the whole document
+        let range = text_document
+            .apply_change(&TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: "hello\n\u{20AC}".to_string(),
+            })
+            .unwrap();
+        assert_eq!(range, Range::new(Position::new(0, 0), Position::new(1, 1)));
+    }
 
-        // E euro
-        //   ^
-        let range = Range {
-            start: Position {
-                line: 0,
-                character: 2,
-            },
-            end: Position {
-                line: 0,
-                character: 3,
-            },
-        };
-        let content = text_document.get_content(Some(range));
-        assert_eq!(content, "e");
+    #[test]
+    fn test_apply_change_at_lone_cr() {
+        let change =
+            |start: (u32, u32), end: (u32, u32), text: &str| TextDocumentContentChangeEvent {
+                range: Some(Range::new(
+                    Position::new(start.0, start.1),
+                    Position::new(end.0, end.1),
+                )),
+                range_length: None,
+                text: text.to_string(),
+            };
+        let cases = [
+            // before and after the `\r`
+            (
+                change((0, 3), (0, 3), "x"),
+                "foox\rbar",
+                vec![0, 5],
+                ((0, 3), (0, 4)),
+            ),
+            (
+                change((1, 0), (1, 0), "x"),
+                "foo\rxbar",
+                vec![0, 4],
+                ((1, 0), (1, 1)),
+            ),
+            // beyond the whole of line 0 is the end of line 0
+            (
+                change((0, 9), (0, 9), "x"),
+                "foox\rbar",
+                vec![0, 5],
+                ((0, 3), (0, 4)),
+            ),
+            // joining the lines
+            (
+                change((0, 3), (1, 0), ""),
+                "foobar",
+                vec![0],
+                ((0, 3), (0, 3)),
+            ),
+            (
+                change((0, 2), (1, 1), "\n"),
+                "fo\nar",
+                vec![0, 3],
+                ((0, 2), (1, 0)),
+            ),
+            // the `\r` becomes part of a `\r\n`, so the inserted `\n` is on line 0
+            (
+                change((1, 0), (1, 0), "\n"),
+                "foo\r\nbar",
+                vec![0, 5],
+                ((0, 4), (1, 0)),
+            ),
+            (
+                change((1, 0), (1, 1), "\nx"),
+                "foo\r\nxar",
+                vec![0, 5],
+                ((0, 4), (1, 1)),
+            ),
+            // inserting another `\r`
+            (
+                change((0, 3), (0, 3), "\r"),
+                "foo\r\rbar",
+                vec![0, 4, 5],
+                ((0, 3), (1, 0)),
+            ),
+        ];
+        for (change, content, line_offsets, (start, end)) in cases {
+            let mut text_document =
+                FullTextDocument::new("js".to_string(), 2, "foo\rbar".to_string());
+            let range = text_document.apply_change(&change).unwrap();
+            assert_eq!(text_document.get_content(None), content);
+            assert_eq!(text_document.line_offsets, line_offsets, "{content:?}");
+            assert_eq!(
+                range,
+                Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1)),
+                "{content:?}"
+            );
+        }
     }
 
-    /// supplementary multilingual plane, aka surrogate pairs
     #[test]
-    fn test_get_content_smp() {
-        // Deseret Small Letter Yee
-        let text_document = FullTextDocument::new("js".to_string(), 2, "\u{10437} yee".to_string());
+    fn test_offset_at_position_at_lone_cr() {
+        let text_document = FullTextDocument::new("js".to_string(), 2, "foo\rbar\r".to_string());
+        for offset in 0..=text_document.content_len() {
+            let position = text_document.position_at(offset);
+            assert_eq!(text_document.offset_at(position), offset);
+        }
+        assert_eq!(text_document.offset_at(Position::new(1, 0)), 4);
+        assert_eq!(text_document.offset_at(Position::new(0, 9)), 4);
+        assert_eq!(text_document.offset_at(Position::new(2, 0)), 8);
+        assert_eq!(text_document.end_position(), Position::new(2, 0));
+    }
 
-        // surrogate pairs are 2 UTF16 code units wide
-        let range = Range {
-            start: Position {
-                line: 0,
-                character: 0,
-            },
-            end: Position {
-                line: 0,
-                character: 2,
-            },
-        };
-        let content = text_document.get_content(Some(range));
-        assert_eq!(content, "\u{10437}");
+    #[test]
+    fn test_update_empty_changes() {
+        let mut text_document = full_text_document();
+        let line_offsets = text_document.line_offsets.as_ptr();
+
+        text_document.update(&[], 3);
+        assert_eq!(text_document.version(), 3);
+        assert_eq!(
+            text_document.get_content(None),
+            "he\nllo\nworld\r\nfoo\rbar"
+        );
+        assert_eq!(text_document.line_offsets, vec![0, 3, 7, 14, 18]);
+        assert_eq!(text_document.line_offsets.as_ptr(), line_offsets);
+
+        assert_eq!(text_document.try_update(&[], 4), Ok(()));
+        assert_eq!(text_document.version(), 4);
     }
 
     #[test]
@@ -703,7 +5684,8 @@ mod tests {
         doc.update(
             &[TextDocumentContentChangeEvent {
                 range: Some(Range {
-                    // After \n at the end of line 1.
+                    // After \n at the end of line 1, which is clamped to the end of
+                    // the line before the \n.
                     start: Position {
                         line: 1,
                         character: 10,
@@ -722,12 +5704,12 @@ mod tests {
         assert_eq!(
             doc.get_content(None),
             concat!(
-                "0:1332533\n0:1332534\n",
+                "0:1332533\n0:1332534",
                 "1:6188912\n1:6188913\n1:6188914\n",
-                "0:1332536\n",
+                "\n0:1332536\n",
             ),
         );
-        assert_eq!(doc.line_offsets, vec!(0, 10, 20, 30, 40, 50, 60));
+        assert_eq!(doc.line_offsets, vec!(0, 10, 29, 39, 49, 50, 60));
     }
 
     #[test]
@@ -756,6 +5738,96 @@ mod tests {
         assert_eq!(doc.line_offsets, vec!(0, 10, 16, 18, 20, 22, 27));
     }
 
+    /// A second incremental update replacing lines in the middle of the document
+    /// used to shift the following line offsets twice.
+    #[test]
+    fn test_line_offsets_after_multi_line_replacement() {
+        let mut doc = FullTextDocument::new(
+            "text".to_string(),
+            0,
+            "0:1332533\n0:1332534\n0:1332535\n0:1332536\n".to_string(),
+        );
+        doc.update(
+            &[TextDocumentContentChangeEvent {
+                range: Some(Range {
+                    start: Position {
+                        line: 1,
+                        character: 0,
+                    },
+                    end: Position {
+                        line: 2,
+                        character: 0,
+                    },
+                }),
+                range_length: None,
+                text: "1:6188912\n1:6188913\n".to_string(),
+            }],
+            1,
+        );
+        assert_eq!(doc.line_offsets, vec!(0, 10, 20, 30, 40, 50));
+        doc.update(
+            &[TextDocumentContentChangeEvent {
+                range: Some(Range {
+                    start: Position {
+                        line: 1,
+                        character: 0,
+                    },
+                    end: Position {
+                        line: 3,
+                        character: 0,
+                    },
+                }),
+                range_length: None,
+                text: "2:1234567\n".to_string(),
+            }],
+            2,
+        );
+        assert_eq!(
+            doc.get_content(None),
+            "0:1332533\n2:1234567\n0:1332535\n0:1332536\n"
+        );
+        assert_eq!(doc.line_offsets, vec!(0, 10, 20, 30, 40));
+        assert_eq!(
+            doc.line_offsets,
+            computed_line_offsets(&doc.content, true, None)
+        );
+    }
+
+    /// A `\r` and a `\n` on both sides of an edit boundary form a single line
+    /// terminator, so edits can join or split line terminators.
+    #[test]
+    fn test_line_offsets_join_and_split_crlf() {
+        // joining "\r" and an inserted "\n"
+        let mut doc = FullTextDocument::new("text".to_string(), 0, "a\rb".to_string());
+        doc.update(
+            &[TextDocumentContentChangeEvent {
+                range: Some(Range::new(Position::new(1, 0), Position::new(1, 0))),
+                range_length: None,
+                text: "\n".to_string(),
+            }],
+            1,
+        );
+        assert_eq!(doc.get_content(None), "a\r\nb");
+        assert_eq!(doc.line_offsets, vec!(0, 3));
+
+        // splitting "\r\n" by inserting in between, which no position addresses
+        doc.replace_offsets(2, 2, "x");
+        assert_eq!(doc.get_content(None), "a\rx\nb");
+        assert_eq!(doc.line_offsets, vec!(0, 2, 4));
+
+        // joining "\r" and "\n" by deleting in between
+        doc.update(
+            &[TextDocumentContentChangeEvent {
+                range: Some(Range::new(Position::new(1, 0), Position::new(1, 1))),
+                range_length: None,
+                text: "".to_string(),
+            }],
+            3,
+        );
+        assert_eq!(doc.get_content(None), "a\r\nb");
+        assert_eq!(doc.line_offsets, vec!(0, 3));
+    }
+
     /// This tests a regression caused by confusing byte and character offsets.
     /// When [update] was called on a position whose offset points just after a
     /// non-newline when interpreted as bytes, but pointed just after at a
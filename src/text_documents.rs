@@ -1,15 +1,133 @@
-use crate::FullTextDocument;
+use crate::{Document, FullTextDocument, PositionEncoding, StaleVersionPolicy, UpdateError};
 use lsp_types::{
     notification::{
-        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification,
+        DidChangeNotebookDocument, DidChangeTextDocument, DidCloseNotebookDocument,
+        DidCloseTextDocument, DidOpenNotebookDocument, DidOpenTextDocument,
+        DidSaveNotebookDocument, DidSaveTextDocument, Notification,
     },
-    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams, Range, Uri,
+    DidChangeNotebookDocumentParams, DidChangeTextDocumentParams, DidCloseNotebookDocumentParams,
+    DidCloseTextDocumentParams, DidOpenNotebookDocumentParams, DidOpenTextDocumentParams,
+    DidSaveNotebookDocumentParams, DidSaveTextDocumentParams, Range,
+    TextDocumentContentChangeEvent, TextDocumentItem, TextDocumentSyncKind, Uri,
 };
 use serde_json::Value;
-use std::collections::BTreeMap;
+use std::borrow::Cow;
+use std::collections::{btree_map::Entry, BTreeMap, HashMap};
 
-#[derive(Default)]
-pub struct TextDocuments(BTreeMap<Uri, FullTextDocument>);
+/// What [`TextDocuments::listen`] did with a notification
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenOutcome {
+    /// A `textDocument/didOpen` opened the document
+    Opened(Uri),
+    /// A `textDocument/didChange` changed the document
+    Changed(Uri),
+    /// A `textDocument/didChange` targets a document that isn't open, e.g. because
+    /// its Uri is spelled differently than when it was opened, the changes are
+    /// dropped. The server may want to log it or ask the client to resync.
+    ChangedUnknown(Uri),
+    /// A change of a `textDocument/didChange`, or the saved text of a
+    /// `textDocument/didSave`, can not be applied to the document,
+    /// e.g. a change with a range for a document synced with
+    /// [`TextDocumentSyncKind::FULL`] or a stale version. The changes before it stay
+    /// applied, the server may want to ask the client to resync the document. The
+    /// [`TextDocuments::set_on_change`] callback fires if there may be such changes.
+    Rejected(Uri, UpdateError),
+    /// A `textDocument/didSave` saved the document, its content is replaced by the
+    /// saved text when the client includes it
+    Saved(Uri),
+    /// A `textDocument/didClose` closed the document
+    Closed(Uri),
+    /// A `notebookDocument/*` notification opened, changed or closed cells of the
+    /// notebook, see [`TextDocuments::set_notebook_sync`]
+    Notebook(Uri),
+    /// A `notebookDocument/didChange` changed the notebook, but the changes of some of
+    /// its cells can not be applied, which are given with the reason like
+    /// [`ListenOutcome::Rejected`]
+    NotebookRejected(Uri, Vec<(Uri, UpdateError)>),
+    /// A handler registered with [`TextDocuments::register_handler`] handled the
+    /// notification
+    Custom,
+    /// The notification is not handled by `TextDocuments`
+    Ignored,
+}
+
+impl ListenOutcome {
+    /// Whether the notification was handled by `TextDocuments`
+    pub fn is_handled(&self) -> bool {
+        !matches!(self, ListenOutcome::Ignored)
+    }
+
+    /// The Uri of the document the notification was about
+    pub fn uri(&self) -> Option<&Uri> {
+        match self {
+            ListenOutcome::Opened(uri)
+            | ListenOutcome::Changed(uri)
+            | ListenOutcome::ChangedUnknown(uri)
+            | ListenOutcome::Rejected(uri, _)
+            | ListenOutcome::Saved(uri)
+            | ListenOutcome::Closed(uri)
+            | ListenOutcome::Notebook(uri)
+            | ListenOutcome::NotebookRejected(uri, _) => Some(uri),
+            ListenOutcome::Custom | ListenOutcome::Ignored => None,
+        }
+    }
+}
+
+/// The kind of notification a [`TextDocuments::set_on_change`] callback fires for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChangeKind {
+    /// The document was opened
+    Opened,
+    /// The content of the document changed
+    Changed,
+    /// The document was saved
+    Saved,
+    /// The document was closed, the callback sees it one last time
+    Closed,
+}
+
+type OnChange<D> = Box<dyn FnMut(&Uri, ChangeKind, &D)>;
+
+type Handler = Box<dyn FnMut(&Value) -> bool>;
+
+type NormalizeUri = Box<dyn Fn(&Uri) -> Uri>;
+
+/// Whether `method` is a notification about text documents that
+/// [`TextDocuments::listen`] handles itself
+fn is_document_method(method: &str) -> bool {
+    matches!(
+        method,
+        DidOpenTextDocument::METHOD
+            | DidChangeTextDocument::METHOD
+            | DidSaveTextDocument::METHOD
+            | DidCloseTextDocument::METHOD
+    )
+}
+
+/// Whether `method` is a notification about notebook documents
+fn is_notebook_method(method: &str) -> bool {
+    matches!(
+        method,
+        DidOpenNotebookDocument::METHOD
+            | DidChangeNotebookDocument::METHOD
+            | DidSaveNotebookDocument::METHOD
+            | DidCloseNotebookDocument::METHOD
+    )
+}
+
+/// The text documents opened by the client, kept in sync by [`TextDocuments::listen`]
+///
+/// Documents are [`FullTextDocument`]s unless another [`Document`] type is given.
+pub struct TextDocuments<D = FullTextDocument> {
+    documents: BTreeMap<Uri, D>,
+    position_encoding: PositionEncoding,
+    sync_kind: Option<TextDocumentSyncKind>,
+    stale_version_policy: StaleVersionPolicy,
+    notebook_sync: bool,
+    on_change: Option<OnChange<D>>,
+    handlers: HashMap<String, Handler>,
+    normalize_uri: Option<NormalizeUri>,
+}
 
 impl TextDocuments {
     /// Create a text documents
@@ -24,11 +142,326 @@ impl TextDocuments {
     /// let text_documents = TextDocuments::new();
     /// ```
     pub fn new() -> Self {
-        Self(BTreeMap::new())
+        Self::with_encoding(PositionEncoding::default())
     }
 
-    pub fn documents(&self) -> &BTreeMap<Uri, FullTextDocument> {
-        &self.0
+    /// Create a text documents whose documents count positions in the given `encoding`,
+    /// usually the one negotiated with the client during `initialize`
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use lsp_textdocument::{PositionEncoding, TextDocuments};
+    ///
+    /// let text_documents = TextDocuments::with_encoding(PositionEncoding::Utf8);
+    /// assert_eq!(text_documents.position_encoding(), PositionEncoding::Utf8);
+    /// ```
+    pub fn with_encoding(position_encoding: PositionEncoding) -> Self {
+        Self {
+            position_encoding,
+            ..Self::default()
+        }
+    }
+}
+
+impl<D> Default for TextDocuments<D> {
+    fn default() -> Self {
+        Self {
+            documents: BTreeMap::new(),
+            position_encoding: PositionEncoding::default(),
+            sync_kind: None,
+            stale_version_policy: StaleVersionPolicy::Apply,
+            notebook_sync: false,
+            on_change: None,
+            handlers: HashMap::new(),
+            normalize_uri: None,
+        }
+    }
+}
+
+impl<D: Document> TextDocuments<D> {
+    /// The encoding positions of newly opened documents are counted in
+    pub fn position_encoding(&self) -> PositionEncoding {
+        self.position_encoding
+    }
+
+    /// Count the positions of documents opened from now on in the given `encoding`,
+    /// e.g. for a text documents of another [`Document`] type created with
+    /// [`Default::default`]
+    pub fn set_position_encoding(&mut self, position_encoding: PositionEncoding) {
+        self.position_encoding = position_encoding;
+    }
+
+    /// Enforce the sync kind negotiated with the client on documents opened from now on,
+    /// see [`FullTextDocument::set_sync_kind`]. A `textDocument/didChange` that doesn't
+    /// match it is reported as [`ListenOutcome::Rejected`].
+    pub fn set_sync_kind(&mut self, sync_kind: TextDocumentSyncKind) {
+        self.sync_kind = Some(sync_kind);
+    }
+
+    /// Configure how documents opened from now on handle a `textDocument/didChange`
+    /// whose version isn't greater than the current one, see
+    /// [`FullTextDocument::set_stale_version_policy`]
+    ///
+    /// Such changes are applied by default, like any other change. Rejected ones are
+    /// reported as [`ListenOutcome::Rejected`], skipped ones like any other change.
+    pub fn set_stale_version_policy(&mut self, stale_version_policy: StaleVersionPolicy) {
+        self.stale_version_policy = stale_version_policy;
+    }
+
+    /// Also track the cells of notebooks, from `notebookDocument/*` notifications
+    ///
+    /// Each cell is tracked as a document at the Uri of the cell, the structure of
+    /// the notebook itself is not tracked. The [`TextDocuments::set_on_change`]
+    /// callback fires for each opened, changed or closed cell.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::{ListenOutcome, TextDocuments};
+    /// use serde_json::json;
+    ///
+    /// let mut text_documents = TextDocuments::new();
+    /// text_documents.set_notebook_sync(true);
+    ///
+    /// let outcome = text_documents.listen("notebookDocument/didOpen", &json!({
+    ///     "notebookDocument": {
+    ///         "uri": "file://example.ipynb",
+    ///         "notebookType": "jupyter-notebook",
+    ///         "version": 1,
+    ///         "cells": [{ "kind": 2, "document": "vscode-notebook-cell://example.ipynb#0" }],
+    ///     },
+    ///     "cellTextDocuments": [{
+    ///         "uri": "vscode-notebook-cell://example.ipynb#0",
+    ///         "languageId": "python",
+    ///         "version": 1,
+    ///         "text": "print('hello')",
+    ///     }],
+    /// }));
+    /// assert!(matches!(outcome, ListenOutcome::Notebook(_)));
+    ///
+    /// let cell = "vscode-notebook-cell://example.ipynb#0".parse().unwrap();
+    /// assert_eq!(text_documents.get_document_content(&cell, None), Some("print('hello')"));
+    /// ```
+    pub fn set_notebook_sync(&mut self, notebook_sync: bool) {
+        self.notebook_sync = notebook_sync;
+    }
+
+    /// Whether `method` is a notification [`TextDocuments::listen`] handles itself
+    fn is_synced_method(&self, method: &str) -> bool {
+        is_document_method(method) || (self.notebook_sync && is_notebook_method(method))
+    }
+
+    /// Register a callback fired whenever [`TextDocuments::listen`] opens, changes,
+    /// saves or closes a tracked document, replacing any previous callback
+    ///
+    /// The callback fires after the document is updated, a closed document is passed
+    /// after it has been removed.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::{ChangeKind, TextDocuments};
+    ///
+    /// let mut text_documents = TextDocuments::new();
+    /// text_documents.set_on_change(|uri, kind, document| {
+    ///     if kind != ChangeKind::Closed {
+    ///         eprintln!("schedule analysis of {} at version {}", uri.as_str(), document.version());
+    ///     }
+    /// });
+    /// ```
+    pub fn set_on_change(&mut self, on_change: impl FnMut(&Uri, ChangeKind, &D) + 'static) {
+        self.on_change = Some(Box::new(on_change));
+    }
+
+    fn fire_on_change(&mut self, uri: &Uri, kind: ChangeKind) {
+        let uri = self.normalized_uri(uri);
+        if let (Some(on_change), Some(document)) =
+            (self.on_change.as_mut(), self.documents.get(&*uri))
+        {
+            on_change(&uri, kind, document);
+        }
+    }
+
+    /// The Uri a document is tracked at, see [`TextDocuments::set_uri_normalizer`]
+    fn normalized_uri<'a>(&self, uri: &'a Uri) -> Cow<'a, Uri> {
+        match &self.normalize_uri {
+            Some(normalize_uri) => Cow::Owned(normalize_uri(uri)),
+            None => Cow::Borrowed(uri),
+        }
+    }
+
+    /// Register a handler for notifications with the given `method`, so that all
+    /// notifications can be passed to [`TextDocuments::listen`]
+    ///
+    /// For other methods than the text document ones, the handler returns whether it
+    /// handled the notification, [`ListenOutcome::Custom`] is returned if it did.
+    /// For text document methods, the handler runs after the document is updated
+    /// and doesn't change the returned outcome. Registering a handler for a method
+    /// again replaces the previous handler.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::{ListenOutcome, TextDocuments};
+    /// use serde_json::json;
+    ///
+    /// let mut text_documents = TextDocuments::new();
+    /// text_documents.register_handler("$/setTrace", |params| params["value"].is_string());
+    ///
+    /// let outcome = text_documents.listen("$/setTrace", &json!({ "value": "verbose" }));
+    /// assert_eq!(outcome, ListenOutcome::Custom);
+    /// let outcome = text_documents.listen("$/setTrace", &json!({}));
+    /// assert_eq!(outcome, ListenOutcome::Ignored);
+    /// ```
+    pub fn register_handler(
+        &mut self,
+        method: &str,
+        handler: impl FnMut(&Value) -> bool + 'static,
+    ) {
+        self.handlers.insert(method.to_string(), Box::new(handler));
+    }
+
+    fn listen_custom(&mut self, method: &str, params: &Value) -> ListenOutcome {
+        match self.handlers.get_mut(method).map(|handler| handler(params)) {
+            Some(true) => ListenOutcome::Custom,
+            _ => ListenOutcome::Ignored,
+        }
+    }
+
+    /// Track documents at the Uri `normalize_uri` returns for the Uri of a
+    /// notification or a lookup, so that the spellings of a Uri that clients may mix
+    /// up, e.g. percent-encoding or the case of a Windows drive letter, refer to the
+    /// same document
+    ///
+    /// Uris are used as is by default. The documents tracked so far are moved to
+    /// their normalized Uri. When several of them end up at the same Uri, the one
+    /// with the greatest version is kept, or the first one in Uri order on a tie, and
+    /// the others are returned along with their previous Uri.
+    ///
+    /// [`TextDocuments::iter`] and the [`TextDocuments::set_on_change`] callback see
+    /// normalized Uris, the Uri of a [`ListenOutcome`] is the one of the notification.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::TextDocuments;
+    /// use lsp_types::Uri;
+    /// use serde_json::json;
+    ///
+    /// let mut text_documents = TextDocuments::new();
+    /// // the drive letters VS Code sends are lowercase and percent-encoded
+    /// text_documents.set_uri_normalizer(|uri: &Uri| {
+    ///     let uri = uri.as_str().replace("%3A", ":").replace("file:///C:", "file:///c:");
+    ///     uri.parse().unwrap()
+    /// });
+    /// text_documents.listen("textDocument/didOpen", &json!({
+    ///     "textDocument": { "uri": "file:///C:/example.txt", "languageId": "plaintext", "version": 1, "text": "hello" }
+    /// }));
+    ///
+    /// let uri = "file:///c%3A/example.txt".parse().unwrap();
+    /// assert_eq!(text_documents.get_document_content(&uri, None), Some("hello"));
+    /// ```
+    #[allow(clippy::mutable_key_type)]
+    pub fn set_uri_normalizer(
+        &mut self,
+        normalize_uri: impl Fn(&Uri) -> Uri + 'static,
+    ) -> Vec<(Uri, D)> {
+        let mut dropped = Vec::new();
+        let mut documents = BTreeMap::new();
+        for (uri, document) in std::mem::take(&mut self.documents) {
+            match documents.entry(normalize_uri(&uri)) {
+                Entry::Vacant(entry) => {
+                    entry.insert((uri, document));
+                }
+                Entry::Occupied(mut entry) => {
+                    if document.version() > entry.get().1.version() {
+                        dropped.push(entry.insert((uri, document)));
+                    } else {
+                        dropped.push((uri, document));
+                    }
+                }
+            }
+        }
+        self.documents = documents
+            .into_iter()
+            .map(|(uri, (_, document))| (uri, document))
+            .collect();
+        self.normalize_uri = Some(Box::new(normalize_uri));
+        dropped
+    }
+
+    #[allow(clippy::mutable_key_type)]
+    pub fn documents(&self) -> &BTreeMap<Uri, D> {
+        &self.documents
+    }
+
+    /// An iterator over the tracked documents and their Uri, ordered by Uri
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::{FullTextDocument, TextDocuments};
+    /// use lsp_types::Uri;
+    ///
+    /// let mut text_documents = TextDocuments::new();
+    /// let uri: Uri = "file://example.txt".parse().unwrap();
+    /// let document = FullTextDocument::new("plaintext".to_string(), 1, "hello rust!".to_string());
+    /// text_documents.insert_document(uri, document);
+    ///
+    /// for (uri, document) in text_documents.iter() {
+    ///     assert_eq!(uri.as_str(), "file://example.txt");
+    ///     assert_eq!(document.get_content(None), "hello rust!");
+    /// }
+    /// assert_eq!(text_documents.len(), 1);
+    /// assert!(!text_documents.is_empty());
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item = (&Uri, &D)> {
+        self.documents.iter()
+    }
+
+    /// The amount of tracked documents
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// Whether no documents are tracked
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Whether the document at the given Uri is tracked
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::{FullTextDocument, TextDocuments};
+    /// use lsp_types::Uri;
+    ///
+    /// let mut text_documents = TextDocuments::new();
+    /// let uri: Uri = "file://example.txt".parse().unwrap();
+    /// assert!(!text_documents.contains(&uri));
+    ///
+    /// let document = FullTextDocument::new("plaintext".to_string(), 1, "hello rust!".to_string());
+    /// text_documents.insert_document(uri.clone(), document);
+    /// assert!(text_documents.contains(&uri));
+    /// assert_eq!(text_documents.uris().collect::<Vec<_>>(), vec![&uri]);
+    /// ```
+    pub fn contains(&self, uri: &Uri) -> bool {
+        self.documents.contains_key(&*self.normalized_uri(uri))
+    }
+
+    /// An iterator over the Uri of the tracked documents, in order
+    pub fn uris(&self) -> impl Iterator<Item = &Uri> {
+        self.documents.keys()
     }
 
     /// Get specify document by giving Uri
@@ -44,8 +477,98 @@ impl TextDocuments {
     /// let uri:Uri = "file://example.txt".parse().unwrap();
     /// text_documents.get_document(&uri);
     /// ```
-    pub fn get_document(&self, uri: &Uri) -> Option<&FullTextDocument> {
-        self.0.get(uri)
+    pub fn get_document(&self, uri: &Uri) -> Option<&D> {
+        self.documents.get(&*self.normalized_uri(uri))
+    }
+
+    /// Get specify document mutably by giving Uri, e.g. to keep it in sync with
+    /// edits applied by the server itself
+    ///
+    /// # Examples:
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::TextDocuments;
+    /// use lsp_types::{TextDocumentContentChangeEvent, Uri};
+    ///
+    /// let mut text_documents = TextDocuments::new();
+    /// let uri: Uri = "file://example.txt".parse().unwrap();
+    /// if let Some(document) = text_documents.get_document_mut(&uri) {
+    ///     let change = TextDocumentContentChangeEvent {
+    ///         range: None,
+    ///         range_length: None,
+    ///         text: "formatted".to_string(),
+    ///     };
+    ///     document.update(&[change], document.version() + 1);
+    /// }
+    /// ```
+    pub fn get_document_mut(&mut self, uri: &Uri) -> Option<&mut D> {
+        self.documents.get_mut(&*self.normalized_uri(uri))
+    }
+
+    /// Track a document that doesn't come from the client, e.g. a file read from disk,
+    /// returning the document previously tracked at this Uri
+    ///
+    /// # Examples:
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::{FullTextDocument, TextDocuments};
+    /// use lsp_types::Uri;
+    ///
+    /// let mut text_documents = TextDocuments::new();
+    /// let uri: Uri = "file://example.txt".parse().unwrap();
+    /// let document = FullTextDocument::new("plaintext".to_string(), 1, "hello rust!".to_string());
+    /// text_documents.insert_document(uri.clone(), document);
+    /// assert_eq!(text_documents.get_document_content(&uri, None), Some("hello rust!"));
+    ///
+    /// let removed = text_documents.remove_document(&uri);
+    /// assert!(removed.is_some());
+    /// assert!(text_documents.get_document(&uri).is_none());
+    /// ```
+    pub fn insert_document(&mut self, uri: Uri, document: D) -> Option<D> {
+        let uri = match &self.normalize_uri {
+            Some(normalize_uri) => normalize_uri(&uri),
+            None => uri,
+        };
+        self.documents.insert(uri, document)
+    }
+
+    /// Stop tracking a document, returning it if it was tracked
+    pub fn remove_document(&mut self, uri: &Uri) -> Option<D> {
+        self.documents.remove(&*self.normalized_uri(uri))
+    }
+
+    /// Stop tracking all documents, e.g. when the workspace is reset
+    pub fn clear(&mut self) {
+        self.documents.clear();
+    }
+
+    /// Keep tracking only the documents for which `f` returns `true`
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::{FullTextDocument, TextDocuments};
+    /// use lsp_types::Uri;
+    ///
+    /// let mut text_documents = TextDocuments::new();
+    /// for uri in ["file:///closed/a.txt", "file:///open/b.txt"] {
+    ///     let document = FullTextDocument::new("plaintext".to_string(), 1, String::new());
+    ///     text_documents.insert_document(uri.parse().unwrap(), document);
+    /// }
+    ///
+    /// // the folder `file:///closed` was removed from the workspace
+    /// text_documents.retain(|uri, _| !uri.as_str().starts_with("file:///closed/"));
+    /// let uris: Vec<&str> = text_documents.uris().map(|uri| uri.as_str()).collect();
+    /// assert_eq!(uris, vec!["file:///open/b.txt"]);
+    ///
+    /// text_documents.clear();
+    /// assert!(text_documents.is_empty());
+    /// ```
+    pub fn retain(&mut self, mut f: impl FnMut(&Uri, &D) -> bool) {
+        self.documents.retain(|uri, document| f(uri, document));
     }
 
     /// Get specify document content by giving Range
@@ -71,10 +594,37 @@ impl TextDocuments {
     /// assert_eq!(sub_content, Some("ello rus"));
     /// ```
     pub fn get_document_content(&self, uri: &Uri, range: Option<Range>) -> Option<&str> {
-        self.0.get(uri).map(|document| document.get_content(range))
+        self.documents
+            .get(&*self.normalized_uri(uri))
+            .map(|document| document.get_content(range))
     }
 
-    /// Get specify document's language by giving Uri
+    /// Get specify document content by giving Range, as an owned `String`
+    ///
+    /// Unlike [`TextDocuments::get_document_content`], this doesn't rely on the
+    /// document storing its content as one contiguous `str`.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::{FullTextDocument, TextDocuments};
+    /// use lsp_types::{Position, Range, Uri};
+    ///
+    /// let mut text_documents = TextDocuments::new();
+    /// let uri: Uri = "file://example.txt".parse().unwrap();
+    /// let document = FullTextDocument::new("plaintext".to_string(), 1, "hello rust!".to_string());
+    /// text_documents.insert_document(uri.clone(), document);
+    ///
+    /// let range = Range::new(Position::new(0, 6), Position::new(0, 10));
+    /// let content = text_documents.get_document_content_owned(&uri, Some(range));
+    /// assert_eq!(content, Some("rust".to_string()));
+    /// ```
+    pub fn get_document_content_owned(&self, uri: &Uri, range: Option<Range>) -> Option<String> {
+        self.get_document_content(uri, range).map(str::to_owned)
+    }
+
+    /// Get specify document's version by giving Uri
     ///
     /// # Examples
     ///
@@ -85,15 +635,100 @@ impl TextDocuments {
     ///
     /// let text_documents = TextDocuments::new();
     /// let uri:Uri = "file://example.js".parse().unwrap();
-    /// let language =  text_documents.get_document_language(&uri);
-    /// assert_eq!(language, Some("javascript"));
+    /// let version = text_documents.get_document_version(&uri);
+    /// assert_eq!(version, Some(1));
     /// ```
-    pub fn get_document_language(&self, uri: &Uri) -> Option<&str> {
-        self.0.get(uri).map(|document| document.language_id())
+    pub fn get_document_version(&self, uri: &Uri) -> Option<i32> {
+        self.documents
+            .get(&*self.normalized_uri(uri))
+            .map(|document| document.version())
+    }
+
+    /// Get specify document's line count by giving Uri
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```no_run
+    /// use lsp_textdocument::TextDocuments;
+    /// use lsp_types::Uri;
+    ///
+    /// let text_documents = TextDocuments::new();
+    /// let uri:Uri = "file://example.js".parse().unwrap();
+    /// let line_count = text_documents.get_document_line_count(&uri);
+    /// assert_eq!(line_count, Some(1));
+    /// ```
+    pub fn get_document_line_count(&self, uri: &Uri) -> Option<u32> {
+        self.documents
+            .get(&*self.normalized_uri(uri))
+            .map(|document| document.line_count())
     }
 
     /// Listening the notification from client, you just need to pass `method` and `params`
     ///
+    /// Returns which document was opened, changed or closed, or
+    /// [`ListenOutcome::Ignored`] for notifications that aren't about text documents
+    /// unless a handler is registered for them, see [`TextDocuments::register_handler`].
+    ///
+    /// The changes of a `textDocument/didChange` are applied with
+    /// [`Document::try_update_owned`]. Their version isn't checked unless configured
+    /// with [`TextDocuments::set_stale_version_policy`], which other [`Document`]
+    /// types than [`FullTextDocument`] only honor if they implement it.
+    ///
+    /// # Examples:
+    ///
+    /// Basic usage:
+    /// ```no_run
+    /// use lsp_textdocument::TextDocuments;
+    ///
+    /// let method = "textDocument/didOpen";
+    /// let params = serde_json::to_value("message produced by client").unwrap();
+    ///
+    /// let mut text_documents = TextDocuments::new();
+    /// let accept: bool = text_documents.listen(method, &params).is_handled();
+    /// ```
+    pub fn listen(&mut self, method: &str, params: &Value) -> ListenOutcome {
+        if self.is_synced_method(method) {
+            self.listen_owned(method, params.clone())
+        } else {
+            self.listen_custom(method, params)
+        }
+    }
+
+    /// Listen to several notifications at once, in order, returning the outcome of
+    /// each one, see [`TextDocuments::listen`]
+    ///
+    /// A server can re-analyze once per batch based on the returned outcomes rather
+    /// than once per notification. The [`TextDocuments::set_on_change`] callback
+    /// still fires for each notification.
+    ///
+    /// # Examples:
+    ///
+    /// Basic usage:
+    /// ```
+    /// use lsp_textdocument::{ListenOutcome, TextDocuments};
+    /// use serde_json::json;
+    ///
+    /// let mut text_documents = TextDocuments::new();
+    /// let outcomes = text_documents.listen_batch(&[
+    ///     ("textDocument/didOpen".to_string(), json!({
+    ///         "textDocument": { "uri": "file://example.txt", "languageId": "plaintext", "version": 1, "text": "hello" }
+    ///     })),
+    ///     ("textDocument/didClose".to_string(), json!({ "textDocument": { "uri": "file://example.txt" } })),
+    /// ]);
+    /// assert!(matches!(outcomes[..], [ListenOutcome::Opened(_), ListenOutcome::Closed(_)]));
+    /// assert!(text_documents.is_empty());
+    /// ```
+    pub fn listen_batch(&mut self, notifications: &[(String, Value)]) -> Vec<ListenOutcome> {
+        notifications
+            .iter()
+            .map(|(method, params)| self.listen(method, params))
+            .collect()
+    }
+
+    /// Like [`TextDocuments::listen`], but takes ownership of `params` so they can be
+    /// deserialized without cloning, which matters for large documents
+    ///
     /// # Examples:
     ///
     /// Basic usage:
@@ -104,45 +739,913 @@ impl TextDocuments {
     /// let params = serde_json::to_value("message produced by client").unwrap();
     ///
     /// let mut text_documents = TextDocuments::new();
-    /// let accept: bool = text_documents.listen(method, &params);
+    /// let accept: bool = text_documents.listen_owned(method, params).is_handled();
     /// ```
-    pub fn listen(&mut self, method: &str, params: &Value) -> bool {
+    pub fn listen_owned(&mut self, method: &str, params: Value) -> ListenOutcome {
+        if !self.is_synced_method(method) {
+            return self.listen_custom(method, &params);
+        }
+
+        let handler_params = self.handlers.contains_key(method).then(|| params.clone());
+        let outcome = self.listen_document(method, params);
+        if let (Some(handler), Some(params)) = (self.handlers.get_mut(method), handler_params) {
+            handler(&params);
+        }
+        outcome
+    }
+
+    fn listen_document(&mut self, method: &str, params: Value) -> ListenOutcome {
         match method {
             DidOpenTextDocument::METHOD => {
-                let params: DidOpenTextDocumentParams = serde_json::from_value(params.clone())
+                let params: DidOpenTextDocumentParams = serde_json::from_value(params)
                     .expect("Expect receive DidOpenTextDocumentParams");
+                let uri = params.text_document.uri.clone();
+                self.open_document(params.text_document);
+                ListenOutcome::Opened(uri)
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams = serde_json::from_value(params)
+                    .expect("Expect receive DidChangeTextDocumentParams");
                 let text_document = params.text_document;
-
-                let document = FullTextDocument::new(
-                    text_document.language_id,
+                let changed = self.change_document(
+                    &text_document.uri,
+                    params.content_changes,
                     text_document.version,
-                    text_document.text,
                 );
-                self.0.insert(text_document.uri, document);
-                true
+                match changed {
+                    Ok(true) => ListenOutcome::Changed(text_document.uri),
+                    Ok(false) => ListenOutcome::ChangedUnknown(text_document.uri),
+                    Err(err) => ListenOutcome::Rejected(text_document.uri, err),
+                }
             }
-            DidChangeTextDocument::METHOD => {
-                let params: DidChangeTextDocumentParams = serde_json::from_value(params.clone())
-                    .expect("Expect receive DidChangeTextDocumentParams");
+            DidSaveTextDocument::METHOD => {
+                let params: DidSaveTextDocumentParams = serde_json::from_value(params)
+                    .expect("Expect receive DidSaveTextDocumentParams");
 
-                if let Some(document) = self.0.get_mut(&params.text_document.uri) {
-                    let changes = &params.content_changes;
-                    let version = params.text_document.version;
-                    document.update(changes, version);
-                };
-                true
+                if let (Some(document), Some(text)) = (
+                    self.documents
+                        .get_mut(&*self.normalized_uri(&params.text_document.uri)),
+                    params.text,
+                ) {
+                    // the saved text is authoritative, resync the content with it
+                    if let Err(err) = document.try_resync(text) {
+                        return ListenOutcome::Rejected(params.text_document.uri, err);
+                    }
+                }
+                self.fire_on_change(&params.text_document.uri, ChangeKind::Saved);
+                ListenOutcome::Saved(params.text_document.uri)
             }
             DidCloseTextDocument::METHOD => {
-                let params: DidCloseTextDocumentParams = serde_json::from_value(params.clone())
+                let params: DidCloseTextDocumentParams = serde_json::from_value(params)
                     .expect("Expect receive DidCloseTextDocumentParams");
+                self.close_document(&params.text_document.uri);
+                ListenOutcome::Closed(params.text_document.uri)
+            }
+            DidOpenNotebookDocument::METHOD => {
+                let params: DidOpenNotebookDocumentParams = serde_json::from_value(params)
+                    .expect("Expect receive DidOpenNotebookDocumentParams");
+                for cell in params.cell_text_documents {
+                    self.open_document(cell);
+                }
+                ListenOutcome::Notebook(params.notebook_document.uri)
+            }
+            DidChangeNotebookDocument::METHOD => {
+                let params: DidChangeNotebookDocumentParams = serde_json::from_value(params)
+                    .expect("Expect receive DidChangeNotebookDocumentParams");
 
-                self.0.remove(&params.text_document.uri);
-                true
+                let mut rejected = Vec::new();
+                if let Some(cells) = params.change.cells {
+                    if let Some(structure) = cells.structure {
+                        for cell in structure.did_close.into_iter().flatten() {
+                            self.close_document(&cell.uri);
+                        }
+                        for cell in structure.did_open.into_iter().flatten() {
+                            self.open_document(cell);
+                        }
+                    }
+                    for content in cells.text_content.into_iter().flatten() {
+                        let cell = content.document;
+                        if let Err(err) =
+                            self.change_document(&cell.uri, content.changes, cell.version)
+                        {
+                            rejected.push((cell.uri, err));
+                        }
+                    }
+                }
+                if rejected.is_empty() {
+                    ListenOutcome::Notebook(params.notebook_document.uri)
+                } else {
+                    ListenOutcome::NotebookRejected(params.notebook_document.uri, rejected)
+                }
+            }
+            DidSaveNotebookDocument::METHOD => {
+                let params: DidSaveNotebookDocumentParams = serde_json::from_value(params)
+                    .expect("Expect receive DidSaveNotebookDocumentParams");
+                ListenOutcome::Notebook(params.notebook_document.uri)
+            }
+            DidCloseNotebookDocument::METHOD => {
+                let params: DidCloseNotebookDocumentParams = serde_json::from_value(params)
+                    .expect("Expect receive DidCloseNotebookDocumentParams");
+                for cell in params.cell_text_documents {
+                    self.close_document(&cell.uri);
+                }
+                ListenOutcome::Notebook(params.notebook_document.uri)
             }
             _ => {
                 // ignore other request
-                false
+                ListenOutcome::Ignored
+            }
+        }
+    }
+
+    fn open_document(&mut self, text_document: TextDocumentItem) {
+        let mut document = D::with_encoding(
+            text_document.language_id,
+            text_document.version,
+            text_document.text,
+            self.position_encoding,
+        );
+        if let Some(sync_kind) = self.sync_kind {
+            document.set_sync_kind(sync_kind);
+        }
+        document.set_stale_version_policy(self.stale_version_policy);
+        let uri = self.normalized_uri(&text_document.uri).into_owned();
+        self.documents.insert(uri, document);
+        self.fire_on_change(&text_document.uri, ChangeKind::Opened);
+    }
+
+    /// Apply the changes to the document at `uri`, returns `false` if it isn't open
+    fn change_document(
+        &mut self,
+        uri: &Uri,
+        changes: Vec<TextDocumentContentChangeEvent>,
+        version: i32,
+    ) -> Result<bool, UpdateError> {
+        let Some(document) = self.documents.get_mut(&*self.normalized_uri(uri)) else {
+            return Ok(false);
+        };
+        // the full text of a change is moved into the document rather than copied,
+        // and a change the client got wrong must not bring the server down
+        let len = changes.len();
+        let result = document.try_update_owned(changes, version);
+        // the changes before a rejected one stay applied, unless it is the only one
+        // or the version is stale, which is checked before applying any change
+        let applied = match &result {
+            Ok(()) => true,
+            Err(UpdateError::StaleVersion { .. }) => false,
+            Err(_) => len > 1,
+        };
+        if applied {
+            self.fire_on_change(uri, ChangeKind::Changed);
+        }
+        result.map(|()| true)
+    }
+
+    fn close_document(&mut self, uri: &Uri) {
+        let uri = self.normalized_uri(uri);
+        let removed = self.documents.remove(&*uri);
+        if let (Some(on_change), Some(document)) = (self.on_change.as_mut(), &removed) {
+            on_change(&uri, ChangeKind::Closed, document);
+        }
+    }
+}
+
+impl TextDocuments<FullTextDocument> {
+    /// Get specify document's language by giving Uri
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    /// ```no_run
+    /// use lsp_textdocument::TextDocuments;
+    /// use lsp_types::Uri;
+    ///
+    /// let text_documents = TextDocuments::new();
+    /// let uri:Uri = "file://example.js".parse().unwrap();
+    /// let language =  text_documents.get_document_language(&uri);
+    /// assert_eq!(language, Some("javascript"));
+    /// ```
+    pub fn get_document_language(&self, uri: &Uri) -> Option<&str> {
+        self.documents
+            .get(&*self.normalized_uri(uri))
+            .map(|document| document.language_id())
+    }
+
+    /// Release the excess capacity of every tracked document, see
+    /// [`FullTextDocument::shrink_to_fit`]
+    pub fn shrink_to_fit(&mut self) {
+        for document in self.documents.values_mut() {
+            document.shrink_to_fit();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn uri() -> Uri {
+        "file://example.txt".parse().unwrap()
+    }
+
+    fn opened_text_documents() -> TextDocuments {
+        let mut text_documents = TextDocuments::new();
+        text_documents.listen(
+            DidOpenTextDocument::METHOD,
+            &json!({
+                "textDocument": {
+                    "uri": "file://example.txt",
+                    "languageId": "plaintext",
+                    "version": 1,
+                    "text": "hello rust!",
+                }
+            }),
+        );
+        text_documents
+    }
+
+    #[test]
+    fn test_listen_moves_changes() {
+        /// A document that only accepts owned changes
+        struct OwnedOnly(FullTextDocument);
+
+        impl Document for OwnedOnly {
+            fn new(language_id: String, version: i32, content: String) -> Self {
+                Self(FullTextDocument::new(language_id, version, content))
+            }
+
+            fn update(&mut self, _: &[TextDocumentContentChangeEvent], _: i32) {
+                panic!("The changes should be moved");
+            }
+
+            fn update_owned(&mut self, changes: Vec<TextDocumentContentChangeEvent>, version: i32) {
+                self.0.update_owned(changes, version);
+            }
+
+            fn version(&self) -> i32 {
+                self.0.version()
+            }
+
+            fn get_content(&self, range: Option<Range>) -> &str {
+                self.0.get_content(range)
+            }
+
+            fn line_count(&self) -> u32 {
+                self.0.line_count()
+            }
+
+            fn position_at(&self, offset: u32) -> lsp_types::Position {
+                self.0.position_at(offset)
+            }
+
+            fn offset_at(&self, position: lsp_types::Position) -> u32 {
+                self.0.offset_at(position)
+            }
+        }
+
+        let mut text_documents = TextDocuments::<OwnedOnly>::default();
+        text_documents.listen(
+            DidOpenTextDocument::METHOD,
+            &json!({
+                "textDocument": { "uri": "file://example.txt", "languageId": "plaintext", "version": 1, "text": "hello" }
+            }),
+        );
+        text_documents.listen(
+            DidChangeTextDocument::METHOD,
+            &json!({
+                "textDocument": { "uri": "file://example.txt", "version": 2 },
+                "contentChanges": [{ "text": "hello rust!" }],
+            }),
+        );
+        text_documents.listen(
+            DidSaveTextDocument::METHOD,
+            &json!({ "textDocument": { "uri": "file://example.txt" }, "text": "hello lsp!" }),
+        );
+        assert_eq!(
+            text_documents.get_document_content(&uri(), None),
+            Some("hello lsp!")
+        );
+        assert_eq!(text_documents.get_document_version(&uri()), Some(2));
+    }
+
+    #[test]
+    fn test_get_document_version() {
+        let mut text_documents = opened_text_documents();
+        assert_eq!(text_documents.get_document_version(&uri()), Some(1));
+
+        text_documents.listen(
+            DidChangeTextDocument::METHOD,
+            &json!({
+                "textDocument": { "uri": "file://example.txt", "version": 2 },
+                "contentChanges": [{ "text": "hello lsp!" }],
+            }),
+        );
+        assert_eq!(text_documents.get_document_version(&uri()), Some(2));
+
+        let unknown: Uri = "file://unknown.txt".parse().unwrap();
+        assert_eq!(text_documents.get_document_version(&unknown), None);
+    }
+
+    #[test]
+    fn test_get_document_line_count() {
+        let mut text_documents = opened_text_documents();
+        assert_eq!(text_documents.get_document_line_count(&uri()), Some(1));
+
+        text_documents.listen(
+            DidChangeTextDocument::METHOD,
+            &json!({
+                "textDocument": { "uri": "file://example.txt", "version": 2 },
+                "contentChanges": [{ "text": "hello\nlsp!\n" }],
+            }),
+        );
+        assert_eq!(text_documents.get_document_line_count(&uri()), Some(3));
+
+        let unknown: Uri = "file://unknown.txt".parse().unwrap();
+        assert_eq!(text_documents.get_document_line_count(&unknown), None);
+    }
+
+    #[test]
+    fn test_listen_did_save() {
+        let mut text_documents = opened_text_documents();
+
+        let outcome = text_documents.listen(
+            DidSaveTextDocument::METHOD,
+            &json!({ "textDocument": { "uri": "file://example.txt" } }),
+        );
+        assert_eq!(outcome, ListenOutcome::Saved(uri()));
+        assert_eq!(
+            text_documents.get_document_content(&uri(), None),
+            Some("hello rust!")
+        );
+
+        let outcome = text_documents.listen(
+            DidSaveTextDocument::METHOD,
+            &json!({
+                "textDocument": { "uri": "file://example.txt" },
+                "text": "hello\nlsp!",
+            }),
+        );
+        assert_eq!(outcome, ListenOutcome::Saved(uri()));
+        let document = text_documents.get_document(&uri()).unwrap();
+        assert_eq!(document.get_content(None), "hello\nlsp!");
+        assert_eq!(document.line_offsets(), &[0, 6]);
+        assert_eq!(document.version(), 1);
+    }
+
+    #[test]
+    fn test_listen_did_save_rejected() {
+        /// A document that rejects every change
+        struct ReadOnly(FullTextDocument);
+
+        impl Document for ReadOnly {
+            fn new(language_id: String, version: i32, content: String) -> Self {
+                Self(FullTextDocument::new(language_id, version, content))
+            }
+
+            fn update(&mut self, _: &[TextDocumentContentChangeEvent], _: i32) {
+                panic!("The changes should be tried");
+            }
+
+            fn try_update_owned(
+                &mut self,
+                changes: Vec<TextDocumentContentChangeEvent>,
+                _: i32,
+            ) -> Result<(), UpdateError> {
+                Err(UpdateError::ContentTooLong {
+                    len: changes[0].text.len(),
+                })
+            }
+
+            fn version(&self) -> i32 {
+                self.0.version()
+            }
+
+            fn get_content(&self, range: Option<Range>) -> &str {
+                self.0.get_content(range)
+            }
+
+            fn line_count(&self) -> u32 {
+                self.0.line_count()
             }
+
+            fn position_at(&self, offset: u32) -> lsp_types::Position {
+                self.0.position_at(offset)
+            }
+
+            fn offset_at(&self, position: lsp_types::Position) -> u32 {
+                self.0.offset_at(position)
+            }
+        }
+
+        let mut text_documents = TextDocuments::<ReadOnly>::default();
+        text_documents.listen(
+            DidOpenTextDocument::METHOD,
+            &json!({
+                "textDocument": { "uri": "file://example.txt", "languageId": "plaintext", "version": 1, "text": "hello" }
+            }),
+        );
+        let outcome = text_documents.listen(
+            DidSaveTextDocument::METHOD,
+            &json!({ "textDocument": { "uri": "file://example.txt" }, "text": "hello lsp!" }),
+        );
+        assert_eq!(
+            outcome,
+            ListenOutcome::Rejected(uri(), UpdateError::ContentTooLong { len: 10 })
+        );
+        assert_eq!(
+            text_documents.get_document_content(&uri(), None),
+            Some("hello")
+        );
+    }
+
+    #[test]
+    fn test_listen_batch() {
+        let mut text_documents = opened_text_documents();
+        let outcomes = text_documents.listen_batch(&[
+            (
+                DidChangeTextDocument::METHOD.to_string(),
+                json!({
+                    "textDocument": { "uri": "file://example.txt", "version": 2 },
+                    "contentChanges": [{ "text": "hello lsp!" }],
+                }),
+            ),
+            ("$/cancelRequest".to_string(), json!({ "id": 1 })),
+            (
+                DidSaveTextDocument::METHOD.to_string(),
+                json!({ "textDocument": { "uri": "file://example.txt" } }),
+            ),
+        ]);
+        assert_eq!(
+            outcomes,
+            vec![
+                ListenOutcome::Changed(uri()),
+                ListenOutcome::Ignored,
+                ListenOutcome::Saved(uri()),
+            ]
+        );
+        assert_eq!(
+            text_documents.get_document_content(&uri(), None),
+            Some("hello lsp!")
+        );
+        assert_eq!(text_documents.listen_batch(&[]), vec![]);
+    }
+
+    #[test]
+    fn test_register_handler() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut text_documents = opened_text_documents();
+        for method in ["custom/notification", DidCloseTextDocument::METHOD] {
+            let recorded = Rc::clone(&calls);
+            text_documents.register_handler(method, move |params| {
+                recorded.borrow_mut().push(params.clone());
+                params["handled"] == true
+            });
+        }
+
+        let handled = json!({ "handled": true });
+        assert_eq!(
+            text_documents.listen("custom/notification", &handled),
+            ListenOutcome::Custom
+        );
+        let ignored = json!({ "handled": false });
+        assert_eq!(
+            text_documents.listen_owned("custom/notification", ignored.clone()),
+            ListenOutcome::Ignored
+        );
+        assert_eq!(
+            text_documents.listen("unregistered/notification", &json!({})),
+            ListenOutcome::Ignored
+        );
+
+        // document methods are handled first, regardless of the handler's result
+        let close = json!({ "textDocument": { "uri": "file://example.txt" } });
+        assert_eq!(
+            text_documents.listen(DidCloseTextDocument::METHOD, &close),
+            ListenOutcome::Closed(uri())
+        );
+        assert!(text_documents.is_empty());
+
+        assert_eq!(*calls.borrow(), vec![handled, ignored, close]);
+    }
+
+    #[test]
+    fn test_notebook_sync() {
+        let notebook: Uri = "file://example.ipynb".parse().unwrap();
+        let cell = |idx: u32| -> Uri {
+            format!("vscode-notebook-cell://example.ipynb#{idx}")
+                .parse()
+                .unwrap()
+        };
+        let open = json!({
+            "notebookDocument": {
+                "uri": "file://example.ipynb",
+                "notebookType": "jupyter-notebook",
+                "version": 1,
+                "cells": [
+                    { "kind": 2, "document": "vscode-notebook-cell://example.ipynb#0" },
+                    { "kind": 1, "document": "vscode-notebook-cell://example.ipynb#1" },
+                ],
+            },
+            "cellTextDocuments": [
+                { "uri": "vscode-notebook-cell://example.ipynb#0", "languageId": "python", "version": 1, "text": "x = 1" },
+                { "uri": "vscode-notebook-cell://example.ipynb#1", "languageId": "markdown", "version": 1, "text": "# Title" },
+            ],
+        });
+
+        // not synced unless enabled
+        let mut text_documents = TextDocuments::new();
+        assert_eq!(
+            text_documents.listen(DidOpenNotebookDocument::METHOD, &open),
+            ListenOutcome::Ignored
+        );
+        assert!(text_documents.is_empty());
+
+        text_documents.set_notebook_sync(true);
+        assert_eq!(
+            text_documents.listen(DidOpenNotebookDocument::METHOD, &open),
+            ListenOutcome::Notebook(notebook.clone())
+        );
+        assert_eq!(
+            text_documents.get_document_language(&cell(0)),
+            Some("python")
+        );
+        assert_eq!(
+            text_documents.get_document_content(&cell(1), None),
+            Some("# Title")
+        );
+
+        let change = json!({
+            "notebookDocument": { "uri": "file://example.ipynb", "version": 2 },
+            "change": {
+                "cells": {
+                    "structure": {
+                        "array": { "start": 1, "deleteCount": 1, "cells": [
+                            { "kind": 2, "document": "vscode-notebook-cell://example.ipynb#2" },
+                        ] },
+                        "didOpen": [
+                            { "uri": "vscode-notebook-cell://example.ipynb#2", "languageId": "python", "version": 1, "text": "print(x)" },
+                        ],
+                        "didClose": [{ "uri": "vscode-notebook-cell://example.ipynb#1" }],
+                    },
+                    "textContent": [{
+                        "document": { "uri": "vscode-notebook-cell://example.ipynb#0", "version": 2 },
+                        "changes": [{
+                            "range": { "start": { "line": 0, "character": 4 }, "end": { "line": 0, "character": 5 } },
+                            "text": "2",
+                        }],
+                    }],
+                },
+            },
+        });
+        assert_eq!(
+            text_documents.listen(DidChangeNotebookDocument::METHOD, &change),
+            ListenOutcome::Notebook(notebook.clone())
+        );
+        assert_eq!(
+            text_documents.get_document_content(&cell(0), None),
+            Some("x = 2")
+        );
+        assert_eq!(text_documents.get_document_version(&cell(0)), Some(2));
+        assert!(!text_documents.contains(&cell(1)));
+        assert_eq!(
+            text_documents.get_document_content(&cell(2), None),
+            Some("print(x)")
+        );
+
+        // the changes of a cell can not be applied
+        let change = json!({
+            "notebookDocument": { "uri": "file://example.ipynb", "version": 3 },
+            "change": {
+                "cells": {
+                    "textContent": [{
+                        "document": { "uri": "vscode-notebook-cell://example.ipynb#2", "version": 2 },
+                        "changes": [{
+                            "range": { "start": { "line": 0, "character": 4 }, "end": { "line": 0, "character": 1 } },
+                            "text": "",
+                        }],
+                    }],
+                },
+            },
+        });
+        let outcome = text_documents.listen(DidChangeNotebookDocument::METHOD, &change);
+        let err = UpdateError::InvertedRange {
+            start: lsp_types::Position::new(0, 4),
+            start_offset: 4,
+            end: lsp_types::Position::new(0, 1),
+            end_offset: 1,
+        };
+        assert_eq!(
+            outcome,
+            ListenOutcome::NotebookRejected(notebook.clone(), vec![(cell(2), err)])
+        );
+        assert_eq!(outcome.uri(), Some(&notebook));
+        assert_eq!(
+            text_documents.get_document_content(&cell(2), None),
+            Some("print(x)")
+        );
+
+        let save = json!({ "notebookDocument": { "uri": "file://example.ipynb" } });
+        assert_eq!(
+            text_documents.listen(DidSaveNotebookDocument::METHOD, &save),
+            ListenOutcome::Notebook(notebook.clone())
+        );
+
+        let close = json!({
+            "notebookDocument": { "uri": "file://example.ipynb" },
+            "cellTextDocuments": [
+                { "uri": "vscode-notebook-cell://example.ipynb#0" },
+                { "uri": "vscode-notebook-cell://example.ipynb#2" },
+            ],
+        });
+        assert_eq!(
+            text_documents.listen(DidCloseNotebookDocument::METHOD, &close),
+            ListenOutcome::Notebook(notebook)
+        );
+        assert!(text_documents.is_empty());
+    }
+
+    #[test]
+    fn test_listen_change_unknown_document() {
+        let mut text_documents = opened_text_documents();
+        let outcome = text_documents.listen(
+            DidChangeTextDocument::METHOD,
+            &json!({
+                "textDocument": { "uri": "file://unknown.txt", "version": 2 },
+                "contentChanges": [{ "text": "unknown" }],
+            }),
+        );
+        let unknown: Uri = "file://unknown.txt".parse().unwrap();
+        assert_eq!(outcome, ListenOutcome::ChangedUnknown(unknown.clone()));
+        assert!(outcome.is_handled());
+        assert_eq!(outcome.uri(), Some(&unknown));
+        assert!(!text_documents.contains(&unknown));
+        assert_eq!(
+            text_documents.get_document_content(&uri(), None),
+            Some("hello rust!")
+        );
+    }
+
+    #[test]
+    fn test_listen_rejects_incremental_change_of_full_document() {
+        let mut text_documents = TextDocuments::new();
+        text_documents.set_sync_kind(TextDocumentSyncKind::FULL);
+        text_documents.listen(
+            DidOpenTextDocument::METHOD,
+            &json!({
+                "textDocument": { "uri": "file://example.txt", "languageId": "plaintext", "version": 1, "text": "hello rust!" }
+            }),
+        );
+        let changed = Rc::new(RefCell::new(false));
+        let recorded = Rc::clone(&changed);
+        text_documents.set_on_change(move |_, _, _| *recorded.borrow_mut() = true);
+
+        let range = Range::new(
+            lsp_types::Position::new(0, 6),
+            lsp_types::Position::new(0, 10),
+        );
+        let outcome = text_documents.listen(
+            DidChangeTextDocument::METHOD,
+            &json!({
+                "textDocument": { "uri": "file://example.txt", "version": 2 },
+                "contentChanges": [{ "range": range, "text": "lsp" }],
+            }),
+        );
+        assert_eq!(
+            outcome,
+            ListenOutcome::Rejected(uri(), UpdateError::UnexpectedIncrementalChange { range })
+        );
+        assert_eq!(outcome.uri(), Some(&uri()));
+        assert!(!*changed.borrow());
+        assert_eq!(
+            text_documents.get_document_content(&uri(), None),
+            Some("hello rust!")
+        );
+        assert_eq!(text_documents.get_document_version(&uri()), Some(1));
+
+        // full text changes are still applied
+        let outcome = text_documents.listen(
+            DidChangeTextDocument::METHOD,
+            &json!({
+                "textDocument": { "uri": "file://example.txt", "version": 2 },
+                "contentChanges": [{ "text": "hello lsp!" }],
+            }),
+        );
+        assert_eq!(outcome, ListenOutcome::Changed(uri()));
+        assert_eq!(
+            text_documents.get_document_content(&uri(), None),
+            Some("hello lsp!")
+        );
+    }
+
+    #[test]
+    fn test_listen_rejects_change_after_applied_one() {
+        let mut text_documents = opened_text_documents();
+        let changed = Rc::new(RefCell::new(false));
+        let recorded = Rc::clone(&changed);
+        text_documents.set_on_change(move |_, _, _| *recorded.borrow_mut() = true);
+
+        let range = |start: u32, end: u32| {
+            Range::new(
+                lsp_types::Position::new(0, start),
+                lsp_types::Position::new(0, end),
+            )
+        };
+        let outcome = text_documents.listen(
+            DidChangeTextDocument::METHOD,
+            &json!({
+                "textDocument": { "uri": "file://example.txt", "version": 2 },
+                "contentChanges": [
+                    { "range": range(6, 10), "text": "lsp" },
+                    { "range": range(5, 1), "text": "inverted" },
+                ],
+            }),
+        );
+        assert!(matches!(
+            outcome,
+            ListenOutcome::Rejected(_, UpdateError::InvertedRange { .. })
+        ));
+        assert_eq!(
+            text_documents.get_document_content(&uri(), None),
+            Some("hello lsp!")
+        );
+        assert!(*changed.borrow());
+    }
+
+    #[test]
+    fn test_listen_stale_version() {
+        let stale_change = json!({
+            "textDocument": { "uri": "file://example.txt", "version": 1 },
+            "contentChanges": [{ "text": "out of order" }],
+        });
+
+        // applied by default
+        let mut text_documents = opened_text_documents();
+        let outcome = text_documents.listen(DidChangeTextDocument::METHOD, &stale_change);
+        assert_eq!(outcome, ListenOutcome::Changed(uri()));
+        assert_eq!(
+            text_documents.get_document_content(&uri(), None),
+            Some("out of order")
+        );
+        assert_eq!(text_documents.get_document_version(&uri()), Some(1));
+
+        let open = json!({
+            "textDocument": { "uri": "file://example.txt", "languageId": "plaintext", "version": 1, "text": "hello rust!" }
+        });
+        let mut text_documents = TextDocuments::new();
+        text_documents.set_stale_version_policy(StaleVersionPolicy::Reject);
+        text_documents.listen(DidOpenTextDocument::METHOD, &open);
+        let outcome = text_documents.listen(DidChangeTextDocument::METHOD, &stale_change);
+        assert_eq!(
+            outcome,
+            ListenOutcome::Rejected(
+                uri(),
+                UpdateError::StaleVersion {
+                    current: 1,
+                    received: 1
+                }
+            )
+        );
+        assert_eq!(
+            text_documents.get_document_content(&uri(), None),
+            Some("hello rust!")
+        );
+
+        let mut text_documents = TextDocuments::new();
+        text_documents.set_stale_version_policy(StaleVersionPolicy::Skip);
+        text_documents.listen(DidOpenTextDocument::METHOD, &open);
+        let outcome = text_documents.listen(DidChangeTextDocument::METHOD, &stale_change);
+        assert_eq!(outcome, ListenOutcome::Changed(uri()));
+        assert_eq!(
+            text_documents.get_document_content(&uri(), None),
+            Some("hello rust!")
+        );
+        assert_eq!(text_documents.get_document_version(&uri()), Some(1));
+    }
+
+    #[test]
+    fn test_uri_normalizer() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut text_documents = opened_text_documents();
+        let recorded = Rc::clone(&events);
+        text_documents.set_on_change(move |uri, kind, _| {
+            recorded.borrow_mut().push((uri.as_str().to_string(), kind));
+        });
+        text_documents.set_uri_normalizer(|uri| uri.as_str().to_lowercase().parse().unwrap());
+
+        // the document opened before is moved to its normalized Uri
+        let upper: Uri = "file://EXAMPLE.txt".parse().unwrap();
+        assert!(text_documents.contains(&upper));
+        assert_eq!(text_documents.uris().collect::<Vec<_>>(), vec![&uri()]);
+
+        let outcome = text_documents.listen(
+            DidChangeTextDocument::METHOD,
+            &json!({
+                "textDocument": { "uri": "file://Example.TXT", "version": 2 },
+                "contentChanges": [{ "text": "hello lsp!" }],
+            }),
+        );
+        assert_eq!(
+            outcome,
+            ListenOutcome::Changed("file://Example.TXT".parse().unwrap())
+        );
+        assert_eq!(
+            text_documents.get_document_content(&upper, None),
+            Some("hello lsp!")
+        );
+
+        text_documents.listen(
+            DidCloseTextDocument::METHOD,
+            &json!({ "textDocument": { "uri": "file://EXAMPLE.TXT" } }),
+        );
+        assert!(text_documents.is_empty());
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                ("file://example.txt".to_string(), ChangeKind::Changed),
+                ("file://example.txt".to_string(), ChangeKind::Closed),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_uri_normalizer_collision() {
+        let mut text_documents = TextDocuments::new();
+        for (uri, version) in [
+            ("file://A.txt", 3),
+            ("file://a.txt", 2),
+            ("file://b.txt", 1),
+        ] {
+            let document = FullTextDocument::new("plaintext".to_string(), version, uri.to_string());
+            text_documents.insert_document(uri.parse().unwrap(), document);
         }
+
+        let dropped =
+            text_documents.set_uri_normalizer(|uri| uri.as_str().to_lowercase().parse().unwrap());
+        let dropped: Vec<_> = dropped
+            .iter()
+            .map(|(uri, document)| (uri.as_str(), document.version()))
+            .collect();
+        assert_eq!(dropped, vec![("file://a.txt", 2)]);
+        assert_eq!(text_documents.len(), 2);
+        let a: Uri = "file://a.txt".parse().unwrap();
+        assert_eq!(
+            text_documents.get_document_content(&a, None),
+            Some("file://A.txt")
+        );
+    }
+
+    #[test]
+    fn test_on_change() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut text_documents = TextDocuments::new();
+        let recorded = Rc::clone(&events);
+        text_documents.set_on_change(move |uri, kind, document| {
+            recorded
+                .borrow_mut()
+                .push((uri.clone(), kind, document.get_content(None).to_string()));
+        });
+
+        text_documents.listen(
+            DidOpenTextDocument::METHOD,
+            &json!({
+                "textDocument": {
+                    "uri": "file://example.txt",
+                    "languageId": "plaintext",
+                    "version": 1,
+                    "text": "hello rust!",
+                }
+            }),
+        );
+        text_documents.listen(
+            DidChangeTextDocument::METHOD,
+            &json!({
+                "textDocument": { "uri": "file://example.txt", "version": 2 },
+                "contentChanges": [{ "text": "hello lsp!" }],
+            }),
+        );
+        // changes to documents that aren't tracked don't fire
+        text_documents.listen(
+            DidChangeTextDocument::METHOD,
+            &json!({
+                "textDocument": { "uri": "file://unknown.txt", "version": 2 },
+                "contentChanges": [{ "text": "unknown" }],
+            }),
+        );
+        text_documents.listen(
+            DidCloseTextDocument::METHOD,
+            &json!({ "textDocument": { "uri": "file://example.txt" } }),
+        );
+
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                (uri(), ChangeKind::Opened, "hello rust!".to_string()),
+                (uri(), ChangeKind::Changed, "hello lsp!".to_string()),
+                (uri(), ChangeKind::Closed, "hello lsp!".to_string()),
+            ]
+        );
     }
 }